@@ -47,7 +47,7 @@ impl WildDocClient {
     pub fn exec(&mut self, xml: &str, input_json: &str) -> std::io::Result<WildDocResult> {
         let mut include_cache = HashMap::new();
 
-        if input_json.len() > 0 {
+        if !input_json.is_empty() {
             self.sock.write_all(input_json.as_bytes())?;
         }
         self.sock.write_all(&[0])?;
@@ -56,6 +56,7 @@ impl WildDocClient {
         self.sock.write_all(&[0])?;
 
         let mut reader = BufReader::new(self.sock.try_clone().unwrap());
+        let mut recv_body = Vec::new();
         loop {
             let mut recv_include = Vec::new();
             if reader.read_until(0, &mut recv_include)? > 0 {
@@ -85,7 +86,7 @@ impl WildDocClient {
 
                                 let len = include_xml.len() as u64;
                                 self.sock.write_all(&len.to_be_bytes())?;
-                                self.sock.write_all(&include_xml)?;
+                                self.sock.write_all(include_xml)?;
                             }
                         }
                     }
@@ -94,23 +95,22 @@ impl WildDocClient {
                         self.sock.write_all(&exists)?;
                     }
                 } else {
-                    break;
+                    // A chunk of the body, which ends with a chunk of length 0.
+                    let mut len: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
+                    reader.read_exact(&mut len)?;
+                    let len = u64::from_be_bytes(len) as usize;
+                    if len == 0 {
+                        break;
+                    }
+                    let begin = recv_body.len();
+                    recv_body.resize(begin + len, 0);
+                    reader.read_exact(&mut recv_body[begin..])?;
                 }
             } else {
-                break;
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
             }
         }
 
-        let mut len: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
-        reader.read_exact(&mut len)?;
-        let len = u64::from_be_bytes(len) as usize;
-
-        let mut recv_body = Vec::<u8>::with_capacity(len);
-        unsafe {
-            recv_body.set_len(len);
-        }
-        reader.read_exact(recv_body.as_mut_slice())?;
-
         let mut recv_options = Vec::new();
         reader.read_until(0, &mut recv_options)?;
         recv_options.remove(recv_options.len() - 1);
@@ -241,6 +241,6 @@ mod tests {
                 </ul>
             </wd:result>
         "#,"").unwrap();
-        println!("{}", std::str::from_utf8(&r.body()).unwrap());
+        println!("{}", std::str::from_utf8(r.body()).unwrap());
     }
 }
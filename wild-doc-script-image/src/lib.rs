@@ -39,18 +39,18 @@ impl<I: IncludeAdaptor + Send> WildDocScript<I> for WdImage {
 
     async fn eval(&mut self, code: &str, stack: &Stack) -> Result<WildDocValue> {
         let splited: Vec<&str> = code.split("?").collect();
-        if let Some(image_var) = splited.get(0) {
+        if let Some(image_var) = splited.first() {
             if let Some(WildDocValue::Binary(image)) = stack.get(&Arc::new(image_var.to_string())) {
                 if let Some(param) = splited.get(1) {
                     if let Ok(mut image) = image::load_from_memory(image) {
                         let mut w = None;
                         let mut h = None;
                         let mut mode: Option<&str> = None;
-                        for p in param.split("&").into_iter() {
+                        for p in param.split("&") {
                             let pp: Vec<&str> = p.split("=").collect();
                             if pp.len() == 2 {
                                 let value = unsafe { pp.get_unchecked(1) };
-                                match unsafe { pp.get_unchecked(0).as_ref() } {
+                                match *unsafe { pp.get_unchecked(0) } {
                                     "w" => {
                                         w = var2u32(value, stack);
                                     }
@@ -79,12 +79,16 @@ impl<I: IncludeAdaptor + Send> WildDocScript<I> for WdImage {
                                 }
                             }
                         }
-                        if w.is_some() && h.is_none() {
-                            let r = image.width() as f32 / w.unwrap() as f32;
-                            h = Some((image.height() as f32 * r) as u32);
-                        } else if w.is_none() && h.is_some() {
-                            let r = image.height() as f32 / h.unwrap() as f32;
-                            w = Some((image.width() as f32 * r) as u32);
+                        match (w, h) {
+                            (Some(width), None) => {
+                                let r = image.width() as f32 / width as f32;
+                                h = Some((image.height() as f32 * r) as u32);
+                            }
+                            (None, Some(height)) => {
+                                let r = image.height() as f32 / height as f32;
+                                w = Some((image.width() as f32 * r) as u32);
+                            }
+                            _ => {}
                         }
                         if let (Some(w), Some(h)) = (w, h) {
                             image = match mode {
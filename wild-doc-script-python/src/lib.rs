@@ -66,7 +66,7 @@ fn wdv(_py: Python, key: String) -> PyResult<PyObject> {
         let stack: &Stack =
             unsafe { PyCapsule::import(py, CString::new("builtins.wdstack")?.as_ref())? };
 
-        if let Some(v) = stack.get(&Arc::new(key.into())) {
+        if let Some(v) = stack.get(&Arc::new(key)) {
             return PyModule::from_code(
                 py,
                 r#"
@@ -12,8 +12,8 @@ pub use stack::Stack;
 pub use value::{SessionSearchResult, Vars, WildDocValue};
 
 pub use semilattice_database_session::{
-    search, Activity, CollectionRow, Condition, CustomOrderKey, CustomSort, DataOption, Depends,
-    FieldName, Order, Pend, SearchResult, Session, SessionCustomOrder, SessionDatabase,
+    search, Activity, Collection, CollectionRow, Condition, CustomOrderKey, CustomSort, DataOption,
+    Depends, FieldName, Order, Pend, SearchResult, Session, SessionCustomOrder, SessionDatabase,
     SessionOrder, SessionOrderKey, SessionRecord, Term, Uuid,
 };
 
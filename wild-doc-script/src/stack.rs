@@ -25,6 +25,15 @@ impl Stack {
         }
         None
     }
+
+    pub fn get_mut(&mut self, key: &Arc<String>) -> Option<&mut WildDocValue> {
+        for vars in self.vars.iter_mut().rev() {
+            if let Some(vars) = vars.get_mut(key) {
+                return Some(vars);
+            }
+        }
+        None
+    }
 }
 
 impl Deref for Stack {
@@ -44,9 +44,7 @@ impl From<serde_json::Value> for WildDocValue {
             serde_json::Value::Bool(v) => Self::Bool(v),
             serde_json::Value::Number(v) => Self::Number(v),
             serde_json::Value::String(v) => Self::String(Arc::new(v)),
-            serde_json::Value::Array(v) => {
-                Self::Array(v.into_iter().map(|v| Self::from(v)).collect())
-            }
+            serde_json::Value::Array(v) => Self::Array(v.into_iter().map(Self::from).collect()),
             serde_json::Value::Object(v) => Self::Object(
                 v.into_iter()
                     .map(|(k, v)| (Arc::new(k), Self::from(v)))
@@ -73,7 +71,7 @@ impl std::fmt::Display for WildDocValue {
             Self::String(v) => v.fmt(f),
             Self::Array(v) => {
                 write!(f, "[")?;
-                let mut iter = v.into_iter();
+                let mut iter = v.iter();
                 if let Some(i) = iter.next() {
                     i.fmt(f)?;
                 }
@@ -121,18 +119,12 @@ impl WildDocValue {
 
     #[inline(always)]
     pub fn is_object(&self) -> bool {
-        match self {
-            Self::Object(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::Object(_))
     }
 
     #[inline(always)]
     pub fn is_null(&self) -> bool {
-        match self {
-            Self::Null => true,
-            _ => false,
-        }
+        matches!(self, Self::Null)
     }
 
     #[inline(always)]
@@ -142,4 +134,23 @@ impl WildDocValue {
             _ => None,
         }
     }
+
+    /// Numbers, and strings that parse as one once trimmed.
+    #[inline(always)]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(v) => v.as_f64(),
+            Self::String(v) => v.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Whole values come back as integers; NaN and infinities become null.
+    pub fn from_f64(value: f64) -> Self {
+        if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+            Self::Number((value as i64).into())
+        } else {
+            serde_json::Number::from_f64(value).map_or(Self::Null, Self::Number)
+        }
+    }
 }
@@ -7,6 +7,7 @@ use std::{
     ops::{Deref, DerefMut},
     sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
 
 use anyhow::Result;
@@ -29,6 +30,13 @@ struct ConfigServer {
     delete_dir_on_start: Option<String>,
     collection: Option<HashMap<String, DataOption>>,
     relation_reserve_unit: Option<u32>,
+    max_iterations: Option<usize>,
+    time_limit_ms: Option<u64>,
+    auto_escape: Option<bool>,
+    minify: Option<bool>,
+    html5: Option<bool>,
+    strict: Option<bool>,
+    max_include_depth: Option<usize>,
 }
 
 struct WildDocWrapper<DI: IncludeAdaptor + Send> {
@@ -89,15 +97,39 @@ fn main() {
                                                     if !std::path::Path::new(dir).exists() {
                                                         std::fs::create_dir_all(dir).unwrap();
                                                     }
+                                                    let mut wd = WildDoc::new(
+                                                        dir,
+                                                        IncludeEmpty::new(),
+                                                        config.collection.clone(),
+                                                        config.relation_reserve_unit.unwrap_or(1),
+                                                    );
+                                                    wd.set_max_iterations(config.max_iterations);
+                                                    wd.set_time_limit(
+                                                        config
+                                                            .time_limit_ms
+                                                            .map(Duration::from_millis),
+                                                    );
+                                                    if let Some(auto_escape) = config.auto_escape {
+                                                        wd.set_auto_escape(auto_escape);
+                                                    }
+                                                    if let Some(minify) = config.minify {
+                                                        wd.set_minify(minify);
+                                                    }
+                                                    if let Some(html5) = config.html5 {
+                                                        wd.set_html5(html5);
+                                                    }
+                                                    if let Some(strict) = config.strict {
+                                                        wd.set_strict(strict);
+                                                    }
+                                                    if let Some(max_include_depth) =
+                                                        config.max_include_depth
+                                                    {
+                                                        wd.set_max_include_depth(Some(
+                                                            max_include_depth,
+                                                        ));
+                                                    }
                                                     Arc::new(Mutex::new(WildDocWrapper {
-                                                        inner: WildDoc::new(
-                                                            dir,
-                                                            IncludeEmpty::new(),
-                                                            config.collection.clone(),
-                                                            config
-                                                                .relation_reserve_unit
-                                                                .unwrap_or(1),
-                                                        ),
+                                                        inner: wd,
                                                     }))
                                                 });
                                             let wd = Arc::clone(&wd);
@@ -143,39 +175,50 @@ fn handler<DI: IncludeAdaptor + Send>(
         }
         xml.remove(xml.len() - 1);
 
-        let ret = wd.clone().lock().unwrap().run_with_include_adaptor(
-            &xml,
-            &input_json,
-            IncludeRemote::new(stream.try_clone().unwrap()),
-        );
-        match ret {
-            Ok(r) => {
-                let body = r.body();
-                let len = body.len() as u64;
-                writer.write_all(&[0])?;
-                writer.write_all(&len.to_be_bytes())?;
-                writer.write_all(body)?;
-                if let Some(response) = r.options().get(&Arc::new("response".into())) {
-                    if let Ok(json) = serde_json::to_string(response) {
-                        writer.write_all(json.as_bytes())?;
-                    } else {
-                        writer.write_all(b"")?;
-                    }
-                } else {
-                    writer.write_all(b"")?;
-                }
-                writer.write_all(&[0])?;
-            }
+        let mut chunks = ChunkWriter(&mut writer);
+        let ret = wd
+            .clone()
+            .lock()
+            .unwrap()
+            .run_to_writer_with_include_adaptor(
+                &xml,
+                &input_json,
+                IncludeRemote::new(stream.try_clone().unwrap()),
+                &mut chunks,
+            );
+        let response = match ret {
+            Ok(options) => options
+                .get(&Arc::new("response".into()))
+                .and_then(|response| serde_json::to_string(response).ok())
+                .unwrap_or_default(),
             Err(e) => {
-                let body = e.to_string();
-                let len = body.len() as u64;
-                writer.write_all(&[0])?;
-                writer.write_all(&len.to_be_bytes())?;
-                writer.write_all(body.as_bytes())?;
-                writer.write_all(b"")?;
-                writer.write_all(&[0])?;
+                // The output rendered before the error has already been sent.
+                chunks.write_all(e.to_string().as_bytes())?;
+                String::new()
             }
-        }
+        };
+        writer.write_all(&[0])?;
+        writer.write_all(&0u64.to_be_bytes())?;
+        writer.write_all(response.as_bytes())?;
+        writer.write_all(&[0])?;
     }
     Ok(())
 }
+
+/// Sends the body as it is rendered, each write as a chunk of 0, the length as u64 and the bytes.
+/// Includes are requested over the same stream between chunks, and a chunk of length 0 ends the body.
+struct ChunkWriter<'a>(&'a mut TcpStream);
+impl Write for ChunkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !buf.is_empty() {
+            self.0.write_all(&[0])?;
+            self.0.write_all(&(buf.len() as u64).to_be_bytes())?;
+            self.0.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
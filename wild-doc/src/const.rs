@@ -3,12 +3,14 @@ use std::sync::Arc;
 
 macro_rules! def {
     ($var:ident,$val:expr) => {
-        pub const $var: Lazy<Arc<String>> = Lazy::new(|| Arc::new($val.into()));
+        pub static $var: Lazy<Arc<String>> = Lazy::new(|| Arc::new($val.into()));
     };
 }
 
 def!(_BLANK, "");
 def!(ACTIVITY, "activity");
+def!(APPEND, "append");
+def!(AVG, "avg");
 def!(BASE64, "base64");
 def!(CLEAR_ON_CLOSE, "clear_on_close");
 def!(COLLECTION, "collection");
@@ -18,6 +20,7 @@ def!(COMMIT, "commit");
 def!(COMMIT_ON_CLOSE, "commit_on_close");
 def!(COMMIT_ROWS, "commit_rows");
 def!(CONTINUE, "continue");
+def!(COUNT, "count");
 def!(CURRENT, "current");
 def!(CURSOR, "cursor");
 def!(
@@ -26,30 +29,61 @@ def!(
 );
 def!(DELETE, "delete");
 def!(DEPENDS, "depends");
+def!(EVEN, "even");
+def!(ESCAPE, "escape");
 def!(EXPIRE, "expire");
 def!(FIELD, "field");
 def!(FIELDS, "fields");
+def!(FILTER, "filter");
+def!(FIRST, "first");
+def!(GROUP, "group");
+def!(GROUPS, "groups");
+def!(HEADER, "header");
 def!(IN, "in");
+def!(INCREMENT, "increment");
+def!(INDEX0, "index0");
+def!(INDEX1, "index1");
 def!(INHERIT_DEPEND_IF_EMPTY, "inherit_depend_if_empty");
 def!(INITIALIZE, "initialize");
 def!(KEY, "key");
+def!(LAST, "last");
 def!(LAST_UPDATED, "last_updated");
+def!(LEN, "len");
+def!(LENGTH, "length");
+def!(LIMIT, "limit");
+def!(LOOP, "loop");
 def!(MAX, "max");
+def!(MERGE, "merge");
 def!(METHOD, "method");
+def!(MIN, "min");
 def!(NAME, "name");
+def!(NOT, "not");
+def!(ODD, "odd");
+def!(OFFSET, "offset");
 def!(ORDER, "order");
+def!(PAGE, "page");
+def!(PAGES, "pages");
+def!(PARAMS, "params");
+def!(PARENT, "parent");
+def!(PER_PAGE, "per_page");
+def!(RAW, "raw");
+def!(REGEX, "regex");
 def!(RELATION, "relation");
 def!(RESULT, "result");
 def!(ROW, "row");
+def!(ROWS, "rows");
 def!(SERIAL, "serial");
 def!(SESSION_ROWS, "session_rows");
 def!(SESSION_SEQUENCE_CURRENT, "session_sequence_current");
 def!(SESSION_SEQUENCE_MAX, "session_sequence_max");
 def!(SRC, "src");
+def!(STEP, "step");
 def!(SUBSTITUTE, "substitute");
+def!(SUM, "sum");
 def!(TERM, "term");
 def!(TERM_BEGIN, "term_begin");
 def!(TERM_END, "term_end");
+def!(TOTAL, "total");
 def!(UPDATE, "update");
 def!(UUID, "uuid");
 def!(VAR, "var");
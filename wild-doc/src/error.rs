@@ -0,0 +1,254 @@
+use std::{error, fmt, sync::Arc, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: Arc<String>,
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.as_str() != "" {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub enum WildDocError {
+    Script {
+        location: Location,
+        tag: String,
+        script: String,
+        cause: anyhow::Error,
+    },
+    Tag {
+        location: Location,
+        tag: String,
+        cause: anyhow::Error,
+    },
+    UnknownTag {
+        location: Location,
+        tag: String,
+    },
+    MissingAttribute {
+        location: Location,
+        tag: String,
+        attribute: String,
+    },
+    LimitExceeded {
+        location: Location,
+        tag: String,
+        limit: Limit,
+    },
+    IncludeDepth {
+        location: Location,
+        tag: String,
+        max_depth: usize,
+        chain: Vec<Arc<String>>,
+    },
+    IncludeCycle {
+        location: Location,
+        tag: String,
+        chain: Vec<Arc<String>>,
+    },
+    Other(anyhow::Error),
+}
+impl WildDocError {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Self::Script { location, .. }
+            | Self::Tag { location, .. }
+            | Self::UnknownTag { location, .. }
+            | Self::MissingAttribute { location, .. }
+            | Self::LimitExceeded { location, .. }
+            | Self::IncludeDepth { location, .. }
+            | Self::IncludeCycle { location, .. } => Some(location),
+            Self::Other(_) => None,
+        }
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Self::Script { tag, .. }
+            | Self::Tag { tag, .. }
+            | Self::UnknownTag { tag, .. }
+            | Self::MissingAttribute { tag, .. }
+            | Self::LimitExceeded { tag, .. }
+            | Self::IncludeDepth { tag, .. }
+            | Self::IncludeCycle { tag, .. } => Some(tag),
+            Self::Other(_) => None,
+        }
+    }
+}
+impl fmt::Display for WildDocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Script {
+                location,
+                tag,
+                script,
+                cause,
+            } => write!(
+                f,
+                "{} <{}> {} script error: {:#}",
+                location, tag, script, cause
+            ),
+            Self::Tag {
+                location,
+                tag,
+                cause,
+            } => write!(f, "{} <{}> {:#}", location, tag, cause),
+            Self::UnknownTag { location, tag } => write!(f, "{} unknown tag <{}>", location, tag),
+            Self::MissingAttribute {
+                location,
+                tag,
+                attribute,
+            } => write!(
+                f,
+                "{} <{}> required attribute \"{}\" is missing",
+                location, tag, attribute
+            ),
+            Self::LimitExceeded {
+                location,
+                tag,
+                limit,
+            } => write!(f, "{} <{}> exceeded {}", location, tag, limit),
+            Self::IncludeDepth {
+                location,
+                tag,
+                max_depth,
+                chain,
+            } => write!(
+                f,
+                "{} <{}> include depth exceeds {}: {}",
+                location,
+                tag,
+                max_depth,
+                include_chain(chain)
+            ),
+            Self::IncludeCycle {
+                location,
+                tag,
+                chain,
+            } => write!(
+                f,
+                "{} <{}> include cycle: {}",
+                location,
+                tag,
+                include_chain(chain)
+            ),
+            Self::Other(cause) => write!(f, "{:#}", cause),
+        }
+    }
+}
+impl error::Error for WildDocError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Script { cause, .. } | Self::Tag { cause, .. } | Self::Other(cause) => {
+                Some(cause.as_ref())
+            }
+            Self::UnknownTag { .. }
+            | Self::MissingAttribute { .. }
+            | Self::LimitExceeded { .. }
+            | Self::IncludeDepth { .. }
+            | Self::IncludeCycle { .. } => None,
+        }
+    }
+}
+impl From<anyhow::Error> for WildDocError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<WildDocError>() {
+            Ok(e) => e,
+            Err(e) => Self::Other(e),
+        }
+    }
+}
+
+fn include_chain(chain: &[Arc<String>]) -> String {
+    chain
+        .iter()
+        .map(|v| v.as_str())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[derive(Debug, Clone)]
+pub enum Limit {
+    Iterations(usize),
+    Time(Duration),
+}
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Iterations(max) => write!(f, "the maximum of {} iterations", max),
+            Self::Time(time_limit) => write!(f, "the time limit of {:?}", time_limit),
+        }
+    }
+}
+impl error::Error for Limit {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ScriptError {
+    pub(crate) script: String,
+    pub(crate) cause: anyhow::Error,
+}
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} script error: {:#}", self.script, self.cause)
+    }
+}
+impl error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.cause.as_ref())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct MissingAttributeError(pub(crate) String);
+impl fmt::Display for MissingAttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "required attribute \"{}\" is missing", self.0)
+    }
+}
+impl error::Error for MissingAttributeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct IncludeDepthError(pub(crate) usize, pub(crate) Vec<Arc<String>>);
+impl fmt::Display for IncludeDepthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "include depth exceeds {}: {}",
+            self.0,
+            include_chain(&self.1)
+        )
+    }
+}
+impl error::Error for IncludeDepthError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct IncludeCycleError(pub(crate) Vec<Arc<String>>);
+impl fmt::Display for IncludeCycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "include cycle: {}", include_chain(&self.0))
+    }
+}
+impl error::Error for IncludeCycleError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeSet,
+    num::{NonZeroI32, NonZeroU32},
+};
+
+use hashbrown::HashMap;
+use wild_doc_script::{Collection, FieldName};
+
+use crate::parser::collation_key_ja;
+
+/// Inverted index of single characters and bigrams of field values, per collection and field.
+/// Bigrams work for CJK text that has no spaces between words.
+/// A field is indexed from the collection on its first fulltext search, and wd:update and session commits keep it up to date.
+#[derive(Default)]
+pub struct FulltextIndex {
+    fields: HashMap<(NonZeroI32, FieldName), FieldIndex>,
+}
+
+#[derive(Default)]
+struct FieldIndex {
+    postings: HashMap<String, BTreeSet<NonZeroU32>>,
+    grams: HashMap<NonZeroU32, Vec<String>>,
+}
+
+impl FulltextIndex {
+    /// Rows of the collection whose field contains every word. The field is indexed first if it is not yet.
+    pub(crate) fn rows(
+        &mut self,
+        collection: &Collection,
+        collection_id: NonZeroI32,
+        field: &FieldName,
+        words: &[String],
+    ) -> Vec<NonZeroU32> {
+        let index = self
+            .fields
+            .entry((collection_id, field.clone()))
+            .or_insert_with(|| {
+                let mut index = FieldIndex::default();
+                for row in collection.all() {
+                    index.insert(row, collection.field_bytes(row, field));
+                }
+                index
+            });
+        // Every gram of a word can be in a row without the word itself, so the values are checked.
+        index
+            .candidates(words)
+            .into_iter()
+            .filter(|row| contains_all(collection.field_bytes(*row, field), words))
+            .collect()
+    }
+
+    /// Reads the indexed fields of the row again from the collection. A deleted row has no values, so it is dropped.
+    pub(crate) fn update(
+        &mut self,
+        collection: &Collection,
+        collection_id: NonZeroI32,
+        row: NonZeroU32,
+    ) {
+        for ((id, field), index) in self.fields.iter_mut() {
+            if *id == collection_id {
+                index.remove(row);
+                index.insert(row, collection.field_bytes(row, field));
+            }
+        }
+    }
+
+    /// Collections that have an indexed field.
+    pub(crate) fn collection_ids(&self) -> BTreeSet<NonZeroI32> {
+        self.fields.keys().map(|(id, _)| *id).collect()
+    }
+
+    pub(crate) fn remove_collection(&mut self, collection_id: NonZeroI32) {
+        self.fields.retain(|(id, _), _| *id != collection_id);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+impl FieldIndex {
+    fn insert(&mut self, row: NonZeroU32, value: &[u8]) {
+        let grams = grams(&collation_key_ja(&String::from_utf8_lossy(value)));
+        if grams.is_empty() {
+            return;
+        }
+        for gram in grams.iter() {
+            self.postings.entry(gram.clone()).or_default().insert(row);
+        }
+        self.grams.insert(row, grams);
+    }
+
+    fn remove(&mut self, row: NonZeroU32) {
+        if let Some(grams) = self.grams.remove(&row) {
+            for gram in grams {
+                if let Some(rows) = self.postings.get_mut(&gram) {
+                    rows.remove(&row);
+                    if rows.is_empty() {
+                        self.postings.remove(&gram);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rows that have every gram of the words.
+    fn candidates(&self, words: &[String]) -> BTreeSet<NonZeroU32> {
+        let mut rows: Option<BTreeSet<NonZeroU32>> = None;
+        for gram in words.iter().flat_map(|word| query_grams(word)) {
+            let postings = self.postings.get(&gram);
+            rows = Some(match (rows, postings) {
+                (None, Some(postings)) => postings.clone(),
+                (Some(rows), Some(postings)) => rows.intersection(postings).cloned().collect(),
+                _ => BTreeSet::new(),
+            });
+        }
+        rows.unwrap_or_default()
+    }
+}
+
+/// Words of a query separated by whitespace, normalized like `COLLATE ja`.
+pub(crate) fn words(query: &str) -> Vec<String> {
+    collation_key_ja(query)
+        .split_whitespace()
+        .map(|v| v.to_owned())
+        .collect()
+}
+
+/// Whether the value contains every word. Words are matched as substrings, so CJK text without spaces works.
+pub(crate) fn contains_all(value: &[u8], words: &[String]) -> bool {
+    let value = collation_key_ja(&String::from_utf8_lossy(value));
+    words.iter().all(|word| value.contains(word.as_str()))
+}
+
+fn grams(value: &str) -> Vec<String> {
+    let mut grams = BTreeSet::new();
+    for word in value.split_whitespace() {
+        let chars: Vec<char> = word.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            grams.insert(c.to_string());
+            if let Some(next) = chars.get(i + 1) {
+                grams.insert([*c, *next].iter().collect());
+            }
+        }
+    }
+    grams.into_iter().collect()
+}
+
+fn query_grams(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    if chars.len() == 1 {
+        vec![word.to_owned()]
+    } else {
+        chars.windows(2).map(|v| v.iter().collect()).collect()
+    }
+}
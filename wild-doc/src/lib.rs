@@ -1,18 +1,24 @@
 mod r#const;
+mod error;
+mod fulltext;
 mod include;
 mod parser;
 mod script;
 mod xml_util;
 
+pub use error::{Limit, Location, WildDocError};
 pub use include::IncludeLocal;
+pub use parser::Template;
 pub use wild_doc_script::{
     search, Activity, CollectionRow, DataOption, FieldName, SessionDatabase, Term,
 };
 
 use std::{
     collections::HashMap,
+    io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Result;
@@ -20,7 +26,8 @@ use parking_lot::{Mutex, RwLock};
 
 use wild_doc_script::{IncludeAdaptor, Vars};
 
-use parser::Parser;
+use fulltext::FulltextIndex;
+use parser::{Options, Output, Parser, TemplateCache};
 
 pub struct WildDocResult {
     body: Vec<u8>,
@@ -40,6 +47,9 @@ pub struct WildDoc<I: IncludeAdaptor + Send> {
     database: Arc<RwLock<SessionDatabase>>,
     default_include_adaptor: Arc<Mutex<I>>,
     cache_dir: PathBuf,
+    templates: Arc<Mutex<TemplateCache>>,
+    fulltext: Arc<Mutex<FulltextIndex>>,
+    options: Options,
 }
 impl<DI: IncludeAdaptor + Send> WildDoc<DI> {
     pub fn new<P: AsRef<Path>>(
@@ -62,18 +72,83 @@ impl<DI: IncludeAdaptor + Send> WildDoc<DI> {
             ))),
             default_include_adaptor: Arc::new(Mutex::new(default_include_adaptor)),
             cache_dir,
+            templates: Arc::new(Mutex::new(TemplateCache::default())),
+            fulltext: Arc::new(Mutex::new(FulltextIndex::default())),
+            options: Options::default(),
         }
     }
 
+    /// In strict mode, unknown wd: tags, unknown elements in wd:search / wd:update,
+    /// missing required attributes and missing var paths are reported as errors instead of being ignored.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    /// When auto escape is on (the default), wd:print escapes values for HTML text,
+    /// or for JS / CSS inside script / style elements. Use raw="true" on wd:print to output a value as is.
+    pub fn set_auto_escape(&mut self, auto_escape: bool) {
+        self.options.auto_escape = auto_escape;
+    }
+
+    /// Aborts a run once wd:for / wd:while iterations and wd:include calls
+    /// exceed `max_iterations` in total.
+    pub fn set_max_iterations(&mut self, max_iterations: Option<usize>) {
+        self.options.limits.max_iterations = max_iterations;
+    }
+
+    /// Aborts a run at the next wd:for / wd:while iteration or wd:include
+    /// once it has run longer than `time_limit`.
+    pub fn set_time_limit(&mut self, time_limit: Option<Duration>) {
+        self.options.limits.time_limit = time_limit;
+    }
+
+    /// Maximum nesting of wd:include and wd:call (16 by default). A file may include itself,
+    /// so this is what stops runaway recursion.
+    pub fn set_max_include_depth(&mut self, max_include_depth: Option<usize>) {
+        self.options.limits.max_include_depth = max_include_depth;
+    }
+
+    /// Collapses whitespace in the text of templates, except in pre, textarea, script, style,
+    /// wd:update and wd:letitgo. Whitespace-only text spanning lines between block-level tags is removed.
+    pub fn set_minify(&mut self, minify: bool) {
+        self.options.minify = minify;
+        self.templates.lock().clear();
+    }
+
+    /// Parses templates as HTML5: void elements such as `img`, `meta` and `link` need no end tag,
+    /// and the content of script, style and textarea is text. Off by default so that XML such as
+    /// RSS keeps `<link>...</link>`; without it only `input`, `br` and `hr` are void.
+    pub fn set_html5(&mut self, html5: bool) {
+        self.options.html5 = html5;
+        self.templates.lock().clear();
+    }
+
+    /// Compiled template of the document. run compiles a document once and reuses it
+    /// while the same content is run again, so this returns the template it uses.
+    pub fn compile(&self, xml: &[u8]) -> Arc<Template> {
+        self.templates.lock().document(
+            Arc::new(xml.to_vec()),
+            self.options.minify,
+            self.options.html5,
+        )
+    }
+
     pub fn database(&self) -> &RwLock<SessionDatabase> {
         &self.database
     }
 
+    /// Drops the fulltext index so that it is built again on the next fulltext search.
+    /// Call it after writing rows through `database()`, which does not update the index.
+    pub fn clear_fulltext_index(&self) {
+        self.fulltext.lock().clear();
+    }
+
     fn run_inner<I: IncludeAdaptor + Send>(
         &mut self,
         xml: &[u8],
         input_json: &[u8],
         include_adaptor: Arc<Mutex<I>>,
+        mut output: Output<'_>,
     ) -> Result<WildDocResult> {
         let mut parser = Parser::new(
             Arc::clone(&self.database),
@@ -88,22 +163,34 @@ impl<DI: IncludeAdaptor + Send> WildDoc<DI> {
                 )]
                 .into(),
             ),
+            Arc::clone(&self.templates),
+            Arc::clone(&self.fulltext),
+            self.options,
         )?;
 
-        let mut pos = 0;
-        let body = tokio::runtime::Builder::new_current_thread()
+        tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .max_blocking_threads(32)
             .build()?
-            .block_on(parser.parse(xml, &mut pos))?;
+            .block_on(parser.parse_document(
+                Arc::new("".into()),
+                Arc::new(xml.to_vec()),
+                &mut output,
+            ))?;
+        let body = output.finish()?;
 
         let options = parser.result_options().clone();
 
         Ok(WildDocResult { body, options })
     }
 
-    pub fn run(&mut self, xml: &[u8], input_json: &[u8]) -> Result<WildDocResult> {
-        self.run_inner(xml, input_json, Arc::clone(&self.default_include_adaptor))
+    pub fn run(&mut self, xml: &[u8], input_json: &[u8]) -> Result<WildDocResult, WildDocError> {
+        Ok(self.run_inner(
+            xml,
+            input_json,
+            Arc::clone(&self.default_include_adaptor),
+            Output::new(),
+        )?)
     }
 
     pub fn run_with_include_adaptor<I: IncludeAdaptor + Send>(
@@ -111,7 +198,50 @@ impl<DI: IncludeAdaptor + Send> WildDoc<DI> {
         xml: &[u8],
         input_json: &[u8],
         include_adaptor: I,
-    ) -> Result<WildDocResult> {
-        self.run_inner(xml, input_json, Arc::new(Mutex::new(include_adaptor)))
+    ) -> Result<WildDocResult, WildDocError> {
+        Ok(self.run_inner(
+            xml,
+            input_json,
+            Arc::new(Mutex::new(include_adaptor)),
+            Output::new(),
+        )?)
+    }
+
+    /// Renders into `writer` while the document is being processed
+    /// instead of holding the whole body in memory, and returns the result options.
+    /// Output is written only between nodes, once at least 8 KiB has been buffered,
+    /// so the content of wd:capture, wd:update and component slots is always held in full before it is written.
+    /// If an error occurs, the output rendered before it has already been written.
+    pub fn run_to_writer<W: Write>(
+        &mut self,
+        xml: &[u8],
+        input_json: &[u8],
+        writer: &mut W,
+    ) -> Result<Vars, WildDocError> {
+        Ok(self
+            .run_inner(
+                xml,
+                input_json,
+                Arc::clone(&self.default_include_adaptor),
+                Output::with_writer(writer),
+            )?
+            .options)
+    }
+
+    pub fn run_to_writer_with_include_adaptor<I: IncludeAdaptor + Send, W: Write>(
+        &mut self,
+        xml: &[u8],
+        input_json: &[u8],
+        include_adaptor: I,
+        writer: &mut W,
+    ) -> Result<Vars, WildDocError> {
+        Ok(self
+            .run_inner(
+                xml,
+                input_json,
+                Arc::new(Mutex::new(include_adaptor)),
+                Output::with_writer(writer),
+            )?
+            .options)
     }
 }
@@ -1,33 +1,66 @@
+mod aggregate;
 mod attr;
+mod block;
 mod case;
 mod collection;
+mod component;
+mod export;
+mod filter;
 mod include;
+mod limit;
 mod r#loop;
+mod options;
+mod output;
+mod page;
+mod print;
 mod record;
 mod search;
 mod session;
+mod set;
 mod sort;
+mod template;
 mod update;
 
-use std::{path::Path, sync::Arc};
+pub(crate) use limit::Limits;
+pub(crate) use options::Options;
+pub(crate) use output::Output;
+pub(crate) use sort::collation_key_ja;
+pub use template::{Template, TemplateCache};
 
-use anyhow::Result;
+use std::{
+    num::NonZeroI32,
+    path::Path,
+    sync::{Arc, Weak},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
 use hashbrown::HashMap;
 use parking_lot::{Mutex, RwLock};
+use regex::Regex;
 
-use maybe_xml::{
-    token::{
-        prop::{Attributes, TagName},
-        Ty,
-    },
-    Reader,
-};
 use wild_doc_script::{
-    IncludeAdaptor, Session, SessionDatabase, Stack, Vars, WildDocScript, WildDocValue,
+    IncludeAdaptor, Session, SessionDatabase, SessionSearchResult, Stack, Vars, WildDocScript,
+    WildDocValue,
+};
+
+use crate::{
+    error::{
+        IncludeCycleError, IncludeDepthError, Limit, Location, MissingAttributeError, ScriptError,
+        WildDocError,
+    },
+    fulltext::FulltextIndex,
+    r#const::*,
+    script::{Expr, Var},
+    xml_util,
 };
 
-use crate::{r#const::*, script::Var, xml_util};
+use block::{Block, Blocks};
+use component::{Component, Slots};
+use print::Escape;
+use r#loop::LoopControl;
+use template::{is_void_element, Attribute, Element, Node};
 
 #[cfg(feature = "js")]
 use wild_doc_script_deno::Deno;
@@ -38,6 +71,47 @@ use wild_doc_script_python::WdPy;
 #[cfg(feature = "image")]
 use wild_doc_script_image::WdImage;
 
+const WD_TAGS: &[&str] = &[
+    "print",
+    "result_option",
+    "print_escape_html",
+    "include",
+    "noparse_include",
+    "delete_collection",
+    "session_gc",
+    "session",
+    "session_sequence_cursor",
+    "sessions",
+    "re",
+    "comment",
+    "letitgo",
+    "update",
+    "on",
+    "search",
+    "sort",
+    "record",
+    "collections",
+    "case",
+    "if",
+    "for",
+    "while",
+    "break",
+    "continue",
+    "tag",
+    "var",
+    "extends",
+    "block",
+    "parent",
+    "define",
+    "call",
+    "slot",
+    "set",
+    "capture",
+    "json",
+    "csv",
+    "aggregate",
+];
+
 struct SessionState {
     session: Session,
     commit_on_close: bool,
@@ -45,13 +119,36 @@ struct SessionState {
 }
 
 pub struct Parser<I: IncludeAdaptor + Send> {
+    /// The async methods of the database borrow it for the whole call, so a guard is held until they complete.
+    /// That cannot deadlock within a run, which is a single future on a current-thread runtime; other runs wait for the guard.
     database: Arc<RwLock<SessionDatabase>>,
     sessions: Vec<SessionState>,
+    // Collections of the session search results made by wd:search, which do not keep it themselves.
+    session_results: Vec<(Weak<SessionSearchResult>, NonZeroI32)>,
     scripts: HashMap<String, Box<dyn WildDocScript<I>>>,
     include_adaptor: Arc<Mutex<I>>,
     stack: Box<Stack>,
     result_options: Vars,
     include_stack: Vec<Arc<String>>,
+    loops: Vec<WildDocValue>,
+    loop_control: Option<LoopControl>,
+    blocks: Vec<Blocks>,
+    block_parents: Vec<Vec<Block>>,
+    components: HashMap<Arc<String>, Component>,
+    slots: Vec<Slots>,
+    templates: Arc<Mutex<TemplateCache>>,
+    fulltext: Arc<Mutex<FulltextIndex>>,
+    // The last wd:on rendered in each wd:update being processed, with its attributes.
+    update_on: Vec<Option<(Element, Vars)>>,
+    regexes: HashMap<Arc<String>, Regex>,
+    strict: bool,
+    auto_escape: bool,
+    minify: bool,
+    html5: bool,
+    escape: Escape,
+    limits: Limits,
+    iterations: usize,
+    started: Instant,
 }
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
@@ -60,14 +157,32 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         include_adaptor: Arc<Mutex<I>>,
         cache_dir: &Path,
         input: Option<Vars>,
+        templates: Arc<Mutex<TemplateCache>>,
+        fulltext: Arc<Mutex<FulltextIndex>>,
+        options: Options,
     ) -> Result<Self> {
+        let Options {
+            strict,
+            auto_escape,
+            minify,
+            html5,
+            limits,
+        } = options;
         let stack = Box::new(Stack::new(input));
         let mut scripts: hashbrown::HashMap<String, Box<dyn WildDocScript<I>>> =
             hashbrown::HashMap::new();
 
         scripts.insert(
             "var".to_owned(),
-            Box::new(Var::new(
+            Box::new(
+                Var::new(Arc::clone(&include_adaptor), cache_dir.to_owned(), &stack)?
+                    .with_strict(strict),
+            ),
+        );
+
+        scripts.insert(
+            "expr".to_owned(),
+            Box::new(Expr::new(
                 Arc::clone(&include_adaptor),
                 cache_dir.to_owned(),
                 &stack,
@@ -107,11 +222,30 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         Ok(Self {
             scripts,
             sessions: vec![],
+            session_results: vec![],
             database,
             include_adaptor,
             stack,
             result_options: Vars::new(),
             include_stack: vec![],
+            loops: vec![],
+            loop_control: None,
+            blocks: vec![],
+            block_parents: vec![],
+            components: HashMap::new(),
+            slots: vec![],
+            templates,
+            fulltext,
+            update_on: vec![],
+            regexes: HashMap::new(),
+            strict,
+            auto_escape,
+            minify,
+            html5,
+            escape: Escape::Html,
+            limits,
+            iterations: 0,
+            started: Instant::now(),
         })
     }
 
@@ -121,298 +255,484 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
 
     async fn parse_wd_start_or_empty_tag(
         &mut self,
-        name: &[u8],
-        attributes: Option<Attributes<'_>>,
-    ) -> Result<Option<Vec<u8>>> {
+        name: &str,
+        attributes: &[Attribute],
+        r: &mut Output<'_>,
+    ) -> Result<bool> {
         match name {
-            b"print" => {
-                return Ok(self.vars_from_attibutes(attributes).await.get(&*VALUE).map(
-                    |v| match v {
-                        WildDocValue::String(s) => s.as_str().to_owned().into(),
-                        WildDocValue::Binary(v) => v.to_vec(),
-                        _ => v.as_string().as_bytes().into(),
-                    },
-                ));
-            }
-            b"result_option" => {
-                let attr = self.vars_from_attibutes(attributes).await;
+            "print" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VALUE])?;
+                return self.print(&attr, r);
+            }
+            "result_option" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VAR])?;
                 if let (Some(var), Some(value)) = (attr.get(&*VAR), attr.get(&*VALUE)) {
                     self.result_options.insert(var.as_string(), value.clone());
                 }
             }
-            b"print_escape_html" => {
-                return Ok(self
-                    .vars_from_attibutes(attributes)
-                    .await
-                    .get(&*VALUE)
-                    .map(|v| xml_util::escape_html(&v.as_string()).into()));
+            "print_escape_html" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VALUE])?;
+                if let Some(v) = attr.get(&*VALUE) {
+                    r.extend(xml_util::escape_html(&v.as_string()).as_bytes());
+                    return Ok(true);
+                }
+            }
+            "include" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*SRC])?;
+                self.get_include_content(attr, true, r).await?;
+                return Ok(true);
             }
-            b"include" => {
-                let attr = self.vars_from_attibutes(attributes).await;
-                return Ok(Some(self.get_include_content(attr, true).await?));
+            "noparse_include" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*SRC])?;
+                self.get_include_content(attr, false, r).await?;
+                return Ok(true);
             }
-            b"noparse_include" => {
-                let attr = self.vars_from_attibutes(attributes).await;
-                return Ok(Some(self.get_include_content(attr, false).await?));
+            "set" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VAR])?;
+                self.set(attr)?;
+                return Ok(true);
             }
-            b"delete_collection" => {
-                let attr = self.vars_from_attibutes(attributes).await;
+            "json" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VALUE])?;
+                self.json(&attr, r)?;
+                return Ok(true);
+            }
+            "csv" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*VALUE, &*FIELDS])?;
+                self.csv(&attr, r)?;
+                return Ok(true);
+            }
+            "delete_collection" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
+                self.required(&attr, &[&*COLLECTION])?;
                 self.delete_collection(attr).await;
             }
-            b"session_gc" => {
-                let attr = self.vars_from_attibutes(attributes).await;
+            "session_gc" => {
+                let attr = self.vars_from_attibutes(attributes).await?;
                 self.session_gc(attr);
             }
+            "break" => {
+                self.loop_control(LoopControl::Break)?;
+                return Ok(true);
+            }
+            "continue" => {
+                self.loop_control(LoopControl::Continue)?;
+                return Ok(true);
+            }
+            "parent" => {
+                self.parent(r).await?;
+                return Ok(true);
+            }
             _ => {}
         }
-        Ok(None)
+        Ok(false)
     }
 
-    fn is_wd_tag(name: &TagName) -> bool {
-        name.namespace_prefix()
-            .map_or(false, |v| v.as_bytes() == b"wd")
+    fn is_known_wd_tag(name: &str) -> bool {
+        WD_TAGS.contains(&name)
     }
 
-    #[async_recursion(?Send)]
-    pub async fn parse(&mut self, xml: &[u8], pos: &mut usize) -> Result<Vec<u8>> {
-        let mut r: Vec<u8> = Vec::new();
-
-        let mut deps = 0;
-        let mut pos_before = 0;
-        let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
-
-        while let Some(token) = reader.tokenize(pos) {
-            match token.ty() {
-                Ty::ProcessingInstruction(pi) => {
-                    if let Some(i) = pi.instructions() {
-                        let target = pi.target();
-                        if let Some(script) = self.scripts.get_mut(target.as_str()) {
-                            if let Err(e) = script
-                                .evaluate_module(
-                                    self.include_stack.last().map_or("", |v| v),
-                                    i.as_str(),
-                                    &self.stack,
-                                )
-                                .await
-                            {
-                                return Err(e);
-                            }
-                        } else {
-                            r.extend(i.as_bytes());
-                        }
-                    }
+    fn current_file(&self) -> Arc<String> {
+        self.include_stack
+            .last()
+            .map_or_else(|| Arc::clone(&_BLANK), Arc::clone)
+    }
+
+    fn location(&self, line: usize, column: usize) -> Location {
+        Location {
+            file: self.current_file(),
+            line,
+            column,
+        }
+    }
+
+    pub(super) fn unknown_tag(&self, element: &Element) -> anyhow::Error {
+        WildDocError::UnknownTag {
+            location: self.location(element.line, element.column),
+            tag: element.name.to_owned(),
+        }
+        .into()
+    }
+
+    fn located_error(&self, element: &Element, e: anyhow::Error) -> anyhow::Error {
+        if e.is::<WildDocError>() {
+            return e;
+        }
+        let location = self.location(element.line, element.column);
+        let tag = element.name.to_owned();
+        let e = match e.downcast::<ScriptError>() {
+            Ok(e) => {
+                return WildDocError::Script {
+                    location,
+                    tag,
+                    script: e.script,
+                    cause: e.cause,
                 }
-                Ty::EmptyElementTag(eet) => {
-                    let name = eet.name();
-                    if name.as_bytes() == b"wd:tag" {
-                        let attr = self.vars_from_attibutes(eet.attributes()).await;
-                        let (name, attr) = self.custom_tag(attr);
-                        r.push(b'<');
-                        r.extend(name.as_str().as_bytes().to_vec());
-                        r.extend(attr);
-                        r.extend(b" />");
-                    } else {
-                        if Self::is_wd_tag(&name) {
-                            if let Some(parsed) = self
-                                .parse_wd_start_or_empty_tag(
-                                    name.local().as_bytes(),
-                                    eet.attributes(),
-                                )
-                                .await?
-                            {
-                                r.extend(parsed);
-                            }
-                        } else {
-                            r.push(b'<');
-                            r.extend(name.as_bytes());
-                            if let Some(attributes) = eet.attributes() {
-                                self.output_attributes(&mut r, attributes).await;
-                            }
-                            r.push(b' ');
-                            r.push(b'/');
-                            r.push(b'>');
-                        }
-                    }
+                .into()
+            }
+            Err(e) => e,
+        };
+        let e = match e.downcast::<MissingAttributeError>() {
+            Ok(e) => {
+                return WildDocError::MissingAttribute {
+                    location,
+                    tag,
+                    attribute: e.0,
+                }
+                .into()
+            }
+            Err(e) => e,
+        };
+        let e = match e.downcast::<Limit>() {
+            Ok(limit) => {
+                return WildDocError::LimitExceeded {
+                    location,
+                    tag,
+                    limit,
+                }
+                .into()
+            }
+            Err(e) => e,
+        };
+        let e = match e.downcast::<IncludeDepthError>() {
+            Ok(IncludeDepthError(max_depth, chain)) => {
+                return WildDocError::IncludeDepth {
+                    location,
+                    tag,
+                    max_depth,
+                    chain,
+                }
+                .into()
+            }
+            Err(e) => e,
+        };
+        match e.downcast::<IncludeCycleError>() {
+            Ok(IncludeCycleError(chain)) => WildDocError::IncludeCycle {
+                location,
+                tag,
+                chain,
+            },
+            Err(cause) => WildDocError::Tag {
+                location,
+                tag,
+                cause,
+            },
+        }
+        .into()
+    }
+
+    /// Compiles markup with the options of the parser.
+    pub(super) fn compile(&self, xml: Vec<u8>) -> Template {
+        Template::with_options(Arc::new(xml), self.minify, self.html5)
+    }
+
+    /// Template of the file, cached by filename. The top-level document has no filename and is not cached.
+    fn template(&self, filename: &Arc<String>, xml: Arc<Vec<u8>>) -> Arc<Template> {
+        let mut templates = self.templates.lock();
+        if filename.is_empty() {
+            templates.document(xml, self.minify, self.html5)
+        } else {
+            templates.file(filename, xml, self.minify, self.html5)
+        }
+    }
+
+    pub(crate) async fn parse_document(
+        &mut self,
+        filename: Arc<String>,
+        xml: Arc<Vec<u8>>,
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let template = self.template(&filename, xml);
+        self.include_stack.push(filename);
+        let ret = self.parse(&template.nodes, r).await;
+        self.include_stack.pop();
+        ret
+    }
+
+    async fn reparse(&mut self, xml: Vec<u8>, r: &mut Output<'_>) -> Result<()> {
+        let template = self.compile(xml);
+        self.parse(&template.nodes, r).await
+    }
+
+    pub(super) async fn parse_to_vec(&mut self, nodes: &[Node]) -> Result<Vec<u8>> {
+        let mut r = Output::new();
+        self.parse(nodes, &mut r).await?;
+        Ok(r.finish()?)
+    }
+
+    #[async_recursion(?Send)]
+    pub(super) async fn parse<'w>(&mut self, nodes: &[Node], r: &mut Output<'w>) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => {
+                    r.extend(text);
                 }
-                Ty::StartTag(st) => {
-                    let name = st.name();
-                    if Self::is_wd_tag(&name) {
-                        if let Some(parsed) = self
-                            .parse_wd_start_or_empty_tag(name.local().as_bytes(), st.attributes())
-                            .await?
+                Node::ProcessingInstruction {
+                    target,
+                    instructions,
+                    line,
+                    column,
+                } => {
+                    if let Some(script) = self.scripts.get_mut(target.as_str()) {
+                        if let Err(cause) = script
+                            .evaluate_module(
+                                self.include_stack.last().map_or("", |v| v.as_str()),
+                                instructions,
+                                &self.stack,
+                            )
+                            .await
                         {
-                            r.extend(parsed);
-                        } else {
-                            deps += 1;
-                            match name.local().as_bytes() {
-                                b"session" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    if let Some(session) = self.session(attr) {
-                                        self.sessions.push(session);
-                                        r.extend(self.parse(xml, pos).await?);
-                                        if let Some(ref mut session_state) = self.sessions.pop() {
-                                            if session_state.commit_on_close {
-                                                self.database
-                                                    .write()
-                                                    .commit(&mut session_state.session)
-                                                    .await;
-                                            } else if session_state.clear_on_close {
-                                                let _ = self
-                                                    .database
-                                                    .write()
-                                                    .session_clear(&mut session_state.session);
-                                            }
-                                        }
-                                    } else {
-                                        r.extend(self.parse(xml, pos).await?);
-                                    }
-                                }
-                                b"session_sequence_cursor" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    let vars = self.session_sequence(attr);
-                                    self.stack.push(vars);
-                                    r.extend(self.parse(xml, pos).await?);
-                                    self.stack.pop();
-                                }
-                                b"sessions" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    let vars = self.sessions(attr);
-                                    self.stack.push(vars);
-                                    r.extend(self.parse(xml, pos).await?);
-                                    self.stack.pop();
-                                }
-                                b"re" => {
-                                    let parsed = self.parse(xml, pos).await?;
-                                    let mut new_pos = 0;
-                                    r.extend(self.parse(&parsed, &mut new_pos).await?);
-                                }
-                                b"comment" => {
-                                    xml_util::to_end(xml, pos);
-                                }
-                                b"letitgo" => {
-                                    let begin = *pos;
-                                    let (inner, _) = xml_util::to_end(xml, pos);
-                                    r.extend(&xml[begin..inner]);
-                                }
-                                b"update" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    self.update(xml, pos, attr).await?;
-                                }
-                                b"on" => {
-                                    let (_, outer) = xml_util::to_end(xml, pos);
-                                    r.extend(&xml[pos_before..outer]);
-                                }
-                                b"search" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    r.extend(self.search(xml, pos, attr).await?);
-                                }
-                                b"sort" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    r.extend(self.sort(xml, pos, attr).await?);
-                                }
-                                b"record" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    let vars = self.record(attr);
-                                    self.stack.push(vars);
-                                    r.extend(self.parse(xml, pos).await?);
-                                    self.stack.pop();
-                                }
-                                b"collections" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    let vars = self.collections(attr);
-                                    self.stack.push(vars);
-                                    r.extend(self.parse(xml, pos).await?);
-                                    self.stack.pop();
-                                }
-                                b"case" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    r.extend(self.case(xml, pos, attr).await?);
-                                }
-                                b"if" => {
-                                    let mut matched = false;
-                                    if let Some(value) =
-                                        self.vars_from_attibutes(st.attributes()).await.get(&*VALUE)
-                                    {
-                                        if value.as_bool().map_or(false, |v| *v) {
-                                            matched = true;
-                                            r.extend(self.parse(xml, pos).await?);
-                                        }
-                                    }
-                                    if matched == false {
-                                        xml_util::to_end(xml, pos);
-                                    }
-                                }
-                                b"for" => {
-                                    let begin = *pos;
-                                    let (inner, _) = xml_util::to_end(xml, pos);
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    r.extend(self.r#for(attr, &xml[begin..inner]).await?);
-                                }
-                                b"while" => {
-                                    let begin = *pos;
-                                    let (inner, _) = xml_util::to_end(xml, pos);
-                                    r.extend(
-                                        self.r#while(st.attributes(), &xml[begin..inner]).await?,
-                                    );
-                                }
-                                b"tag" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    let (name, attr) = self.custom_tag(attr);
-                                    r.push(b'<');
-                                    r.extend(name.as_bytes().to_vec());
-                                    r.extend(attr);
-                                    r.push(b'>');
-
-                                    r.extend(self.parse(xml, pos).await?);
-
-                                    r.extend(b"</");
-                                    r.extend(name.as_bytes().to_vec());
-                                    r.push(b'>');
-                                }
-                                b"var" => {
-                                    let attr = self.vars_from_attibutes(st.attributes()).await;
-                                    self.stack.push(attr);
-                                    r.extend(self.parse(xml, pos).await?);
-                                    self.stack.pop();
-                                }
-                                _ => {}
+                            return Err(WildDocError::Script {
+                                location: self.location(*line, *column),
+                                tag: "?".to_owned() + target,
+                                script: target.to_owned(),
+                                cause,
                             }
+                            .into());
                         }
                     } else {
-                        r.push(b'<');
-                        r.extend(name.as_bytes());
-                        if let Some(attributes) = st.attributes() {
-                            self.output_attributes(&mut r, attributes).await;
-                        }
-                        r.push(b'>');
-                        match name.as_bytes() {
-                            b"input" | b"br" | b"hr" => {}
-                            _ => {
-                                r.extend(self.parse(xml, pos).await?);
-                                r.extend(b"</");
-                                r.extend(name.as_bytes());
-                                r.push(b'>');
-                            }
-                        }
+                        r.extend(instructions.as_bytes());
                     }
                 }
-                Ty::EndTag(_) => {
-                    deps -= 1;
-                    if deps <= 0 {
-                        break;
+                Node::Element(element) => {
+                    if let Err(e) = self.element(element, r).await {
+                        return Err(self.located_error(element, e));
                     }
                 }
-                _ => {
-                    r.extend(token.as_bytes());
+            }
+            r.write_through()?;
+            if self.loop_control.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn element(&mut self, element: &Element, r: &mut Output<'_>) -> Result<()> {
+        if !element.is_wd() {
+            r.push(b'<');
+            r.extend(element.name.as_bytes());
+            self.output_attributes(r, &element.attributes).await?;
+            if element.empty {
+                r.extend(b" />");
+            } else if is_void_element(&element.name, self.html5) {
+                r.push(b'>');
+            } else {
+                r.push(b'>');
+                let escape = self.escape;
+                if let Some(element_escape) = Escape::for_element(&element.name.to_lowercase()) {
+                    self.escape = element_escape;
                 }
+                let ret = self.parse(&element.children, r).await;
+                self.escape = escape;
+                ret?;
+                r.extend(b"</");
+                r.extend(element.name.as_bytes());
+                r.push(b'>');
             }
-            pos_before = *pos;
+            return Ok(());
         }
 
-        Ok(r)
+        let name = element.local();
+        if self.strict && !Self::is_known_wd_tag(name) {
+            // Branches are handled by their parent, so reaching one here means it is misplaced.
+            let parent = match name {
+                "else" => Some("wd:if or wd:case"),
+                "elseif" => Some("wd:if"),
+                "when" => Some("wd:case"),
+                _ => None,
+            };
+            return Err(if let Some(parent) = parent {
+                self.located_error(element, anyhow!("must be directly inside {}", parent))
+            } else {
+                self.unknown_tag(element)
+            });
+        }
+        if self
+            .parse_wd_start_or_empty_tag(name, &element.attributes, r)
+            .await?
+        {
+            return Ok(());
+        }
+        if name == "tag" {
+            let attr = self.vars_from_attibutes(&element.attributes).await?;
+            let (name, attr) = self.custom_tag(&element.attributes, attr)?;
+            r.push(b'<');
+            r.extend(name.as_bytes());
+            r.extend(attr);
+            if element.empty {
+                r.extend(b" />");
+            } else {
+                r.push(b'>');
+                self.parse(&element.children, r).await?;
+                r.extend(b"</");
+                r.extend(name.as_bytes());
+                r.push(b'>');
+            }
+            return Ok(());
+        }
+        if element.empty
+            && !matches!(
+                name,
+                "extends" | "block" | "define" | "call" | "slot" | "capture"
+            )
+        {
+            return Ok(());
+        }
+        match name {
+            "session" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*NAME])?;
+                if let Some(session) = self.session(attr) {
+                    self.sessions.push(session);
+                    self.parse(&element.children, r).await?;
+                    if let Some(ref mut session_state) = self.sessions.pop() {
+                        if session_state.commit_on_close {
+                            self.commit(&mut session_state.session).await;
+                        } else if session_state.clear_on_close {
+                            self.database
+                                .write()
+                                .session_clear(&mut session_state.session);
+                        }
+                    }
+                } else {
+                    self.parse(&element.children, r).await?;
+                }
+            }
+            "session_sequence_cursor" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                let vars = self.session_sequence(attr);
+                self.stack.push(vars);
+                self.parse(&element.children, r).await?;
+                self.stack.pop();
+            }
+            "sessions" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR])?;
+                let vars = self.sessions(attr);
+                self.stack.push(vars);
+                self.parse(&element.children, r).await?;
+                self.stack.pop();
+            }
+            "re" => {
+                let parsed = self.parse_to_vec(&element.children).await?;
+                self.reparse(parsed, r).await?;
+            }
+            "update" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.update(&element.children, attr, r).await?;
+            }
+            "on" => {
+                if self.update_on.is_empty() {
+                    let mut source = vec![];
+                    element.write_source(self.html5, &mut source);
+                    r.extend(&source);
+                } else {
+                    let attr = self.vars_from_attibutes(&element.attributes).await?;
+                    if let Some(on) = self.update_on.last_mut() {
+                        *on = Some((element.clone(), attr));
+                    }
+                }
+            }
+            "search" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*COLLECTION])?;
+                self.search(&element.children, attr, r).await?;
+            }
+            "sort" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR, &*ORDER, &*RESULT])?;
+                self.sort(&element.children, attr, r).await?;
+            }
+            "aggregate" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR, &*RESULT, &*COLLECTION])?;
+                self.aggregate(&element.children, attr, r).await?;
+            }
+            "record" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR, &*COLLECTION, &*ROW])?;
+                let vars = self.record(attr);
+                self.stack.push(vars);
+                self.parse(&element.children, r).await?;
+                self.stack.pop();
+            }
+            "collections" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR])?;
+                let vars = self.collections(attr);
+                self.stack.push(vars);
+                self.parse(&element.children, r).await?;
+                self.stack.pop();
+            }
+            "case" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.case(&element.children, attr, r).await?;
+            }
+            "if" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VALUE])?;
+                self.r#if(&element.children, attr, r).await?;
+            }
+            "for" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR, &*IN])?;
+                self.r#for(attr, &element.children, r).await?;
+            }
+            "while" => {
+                self.r#while(&element.attributes, &element.children, r)
+                    .await?;
+            }
+            "extends" => {
+                self.extends(element, r).await?;
+            }
+            "block" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*NAME])?;
+                self.block(attr, &element.children, r).await?;
+            }
+            "define" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*NAME])?;
+                self.define(attr, &element.children);
+            }
+            "call" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*NAME])?;
+                self.call(attr, &element.children, r).await?;
+            }
+            "slot" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.slot(attr, &element.children, r).await?;
+            }
+            "capture" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.required(&attr, &[&*VAR])?;
+                self.capture(attr, &element.children).await?;
+            }
+            "var" => {
+                let attr = self.vars_from_attibutes(&element.attributes).await?;
+                self.stack.push(attr);
+                self.parse(&element.children, r).await?;
+                self.stack.pop();
+            }
+            _ => {
+                self.parse(&element.children, r).await?;
+            }
+        }
+        Ok(())
     }
 
-    fn custom_tag(&self, vars: Vars) -> (Arc<String>, Vec<u8>) {
+    /// Name and attributes of wd:tag. Values given through a script are escaped by `Escape::attribute_value` like those of other elements.
+    fn custom_tag(&self, attributes: &[Attribute], vars: Vars) -> Result<(Arc<String>, Vec<u8>)> {
         let mut html_attr = vec![];
         let mut name = Arc::new("".into());
         for (key, value) in vars.into_iter() {
@@ -420,26 +740,34 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 name = value.as_string();
             } else if key.starts_with("wd:attr") {
                 let attr = xml_util::quot_unescape(&value.as_string());
-                if attr.len() > 0 {
+                if !attr.is_empty() {
                     html_attr.push(b' ');
                     html_attr.extend(attr.as_bytes());
                 }
             } else {
+                let value = value.as_string();
+                let value = if attributes.iter().any(|attr| attr.name == *key) {
+                    xml_util::escape_html(&xml_util::unescape(&value))
+                } else {
+                    Escape::attribute_value(&key, &value)
+                };
                 html_attr.push(b' ');
                 html_attr.extend(key.as_bytes());
                 html_attr.extend(b"=\"");
-                html_attr.extend(
-                    value
-                        .as_string()
-                        .replace("&", "&amp;")
-                        .replace("<", "&lt;")
-                        .replace(">", "&gt;")
-                        .into_bytes(),
-                );
+                html_attr.extend(value.as_bytes());
                 html_attr.push(b'"');
             }
         }
-
-        (name, html_attr)
+        if !is_tag_name(&name) {
+            return Err(anyhow!("invalid tag name {:?}", name));
+        }
+        Ok((name, html_attr))
     }
 }
+
+/// Whether the name can be written as the name of an element.
+fn is_tag_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+}
@@ -0,0 +1,157 @@
+use std::{
+    num::{NonZeroI32, NonZeroI64, NonZeroU32},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use wild_doc_script::{CollectionRow, IncludeAdaptor, Vars, WildDocValue};
+
+use crate::r#const::*;
+
+use super::{Node, Output, Parser};
+
+#[derive(Default)]
+struct Aggregate {
+    count: usize,
+    numbers: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+impl Aggregate {
+    fn add(&mut self, value: Option<f64>) {
+        self.count += 1;
+        if let Some(value) = value {
+            self.numbers += 1;
+            self.sum += value;
+            self.min = Some(self.min.map_or(value, |v| v.min(value)));
+            self.max = Some(self.max.map_or(value, |v| v.max(value)));
+        }
+    }
+
+    fn into_vars(self) -> Vars {
+        let avg = (self.numbers > 0).then(|| self.sum / self.numbers as f64);
+        let number = |v: Option<f64>| v.map_or(WildDocValue::Null, WildDocValue::from_f64);
+        [
+            (Arc::clone(&COUNT), WildDocValue::Number(self.count.into())),
+            (Arc::clone(&SUM), WildDocValue::from_f64(self.sum)),
+            (Arc::clone(&AVG), number(avg)),
+            (Arc::clone(&MIN), number(self.min)),
+            (Arc::clone(&MAX), number(self.max)),
+        ]
+        .into()
+    }
+}
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// wd:aggregate sets var to {count, sum, avg, min, max} over the rows of result.
+    /// sum, avg, min and max are of the numeric values of field. With group="field.name" or group="depend.key",
+    /// groups has the same object for each value of the field or each row depended on.
+    pub(crate) async fn aggregate(
+        &mut self,
+        nodes: &[Node],
+        attr: Vars,
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let mut vars = Vars::new();
+        if let (Some(var), Some(collection_id)) = (attr.get(&*VAR), self.collection_id(&attr)) {
+            let var = var.as_string();
+            if var.as_str() != "" {
+                let (rows, in_session): (Vec<i64>, bool) = match attr.get(&*RESULT) {
+                    Some(WildDocValue::SearchResult(result)) => (
+                        result.rows().iter().map(|v| v.get().into()).collect(),
+                        false,
+                    ),
+                    Some(WildDocValue::SessionSearchResult(result)) => {
+                        (result.rows().iter().map(|v| v.get()).collect(), true)
+                    }
+                    _ => (vec![], false),
+                };
+                let field = attr.get(&*FIELD).map(|v| v.as_string());
+                let group = attr.get(&*GROUP).map(|v| v.as_string());
+
+                let mut total = Aggregate::default();
+                let mut groups: IndexMap<Arc<String>, Aggregate> = IndexMap::new();
+                for row in rows {
+                    let value = field.as_ref().and_then(|field| {
+                        self.field_bytes(collection_id, row, field, in_session)
+                            .and_then(|v| String::from_utf8_lossy(&v).trim().parse().ok())
+                    });
+                    total.add(value);
+                    if let Some(group) = &group {
+                        let key = if let Some(field) = group.strip_prefix("field.") {
+                            self.field_bytes(collection_id, row, field, in_session)
+                                .map(|v| String::from_utf8_lossy(&v).into_owned())
+                        } else if let Some(key) = group.strip_prefix("depend.") {
+                            self.depend_row(collection_id, row, key, in_session)
+                                .map(|v| v.to_string())
+                        } else {
+                            None
+                        };
+                        groups
+                            .entry(Arc::new(key.unwrap_or_default()))
+                            .or_default()
+                            .add(value);
+                    }
+                }
+
+                let mut value = total.into_vars();
+                if group.is_some() {
+                    value.insert(
+                        Arc::clone(&GROUPS),
+                        WildDocValue::Object(
+                            groups
+                                .into_iter()
+                                .map(|(key, aggregate)| {
+                                    (key, WildDocValue::Object(aggregate.into_vars()))
+                                })
+                                .collect(),
+                        ),
+                    );
+                }
+                vars.insert(var, WildDocValue::Object(value));
+            }
+        }
+        self.stack.push(vars);
+        let ret = self.parse(nodes, r).await;
+        self.stack.pop();
+        ret
+    }
+
+    /// Row depended on with the key, from the temporary collection of the sessions first when in_session.
+    fn depend_row(
+        &self,
+        collection_id: NonZeroI32,
+        row: i64,
+        key: &str,
+        in_session: bool,
+    ) -> Option<i64> {
+        if in_session {
+            for state in self.sessions.iter().rev() {
+                if let Some(temporary_collection) =
+                    state.session.temporary_collection(collection_id)
+                {
+                    if let Some(entity) =
+                        NonZeroI64::new(row).and_then(|row| temporary_collection.get(&row))
+                    {
+                        return entity
+                            .depends()
+                            .iter()
+                            .find(|d| d.key().as_str() == key)
+                            .map(|d| d.row().get().into());
+                    }
+                    break;
+                }
+            }
+        }
+        let row = NonZeroU32::new(u32::try_from(row).ok()?)?;
+        self.database
+            .read()
+            .relation()
+            .depends(None, &CollectionRow::new(collection_id, row))
+            .into_iter()
+            .find(|d| d.key().as_str() == key)
+            .map(|d| d.row().get().into())
+    }
+}
@@ -1,19 +1,25 @@
 use std::sync::Arc;
 
-use maybe_xml::token::prop::Attributes;
+use anyhow::Result;
 use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
 
-use crate::xml_util;
+use crate::{
+    error::{MissingAttributeError, ScriptError},
+    xml_util,
+};
 
-use super::Parser;
+use super::{print::Escape, Attribute, Parser};
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
-    pub(super) async fn output_attributes(&mut self, r: &mut Vec<u8>, attributes: Attributes<'_>) {
-        for attr in attributes.into_iter() {
-            let name = attr.name().as_str();
-            if let Some(value) = attr.value() {
-                let value = value.as_str();
-                let (new_name, new_value) = self.attibute_var_or_script(name, value).await;
+    pub(super) async fn output_attributes(
+        &mut self,
+        r: &mut Vec<u8>,
+        attributes: &[Attribute],
+    ) -> Result<()> {
+        for attr in attributes {
+            let name = attr.name.as_str();
+            if let Some(value) = &attr.value {
+                let (new_name, new_value) = self.attibute_var_or_script(name, value).await?;
                 if new_name == "wd:attr" {
                     if let Some(value) = new_value {
                         if !value.is_null() {
@@ -30,7 +36,7 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                         } else {
                             Self::output_attribute_value(
                                 r,
-                                xml_util::escape_html(&value.as_string()).as_bytes(),
+                                Escape::attribute_value(new_name, &value.as_string()).as_bytes(),
                             );
                         }
                     } else {
@@ -42,6 +48,7 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 r.extend(name.as_bytes().to_vec());
             };
         }
+        Ok(())
     }
 
     fn script_name(name: &str) -> Option<&str> {
@@ -53,43 +60,53 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         }
     }
 
-    #[must_use]
-    pub(super) async fn vars_from_attibutes(&mut self, attributes: Option<Attributes<'_>>) -> Vars {
+    pub(super) async fn vars_from_attibutes(&mut self, attributes: &[Attribute]) -> Result<Vars> {
         let mut r = Vars::new();
 
-        if let Some(attributes) = attributes {
-            for attr in attributes.into_iter() {
-                if let Some(value) = attr.value() {
-                    let name = attr.name().as_str();
-                    if let Some(script_name) = Self::script_name(name) {
-                        if let Some(script) = self.scripts.get_mut(script_name) {
-                            if let Ok(v) = script.eval(value.as_str(), &self.stack).await {
-                                let name = unsafe {
-                                    std::str::from_utf8_unchecked(
-                                        &name.as_bytes()[..name.len() - (script_name.len() + 1)],
-                                    )
-                                };
-                                r.insert(Arc::new(name.to_string()), v);
-                            }
-                        }
-                    } else {
-                        let value = value.as_str();
-                        r.insert(Arc::new(name.into()), {
-                            let value = xml_util::quot_unescape(value);
-                            if let Ok(json) =
-                                serde_json::from_str::<serde_json::Value>(value.as_str())
-                            {
-                                json.into()
-                            } else {
-                                WildDocValue::String(Arc::new(value))
-                            }
-                        });
+        for attr in attributes {
+            if let Some(value) = &attr.value {
+                let name = attr.name.as_str();
+                if let Some(script_name) = Self::script_name(name) {
+                    if let Some(script) = self.scripts.get_mut(script_name) {
+                        let v =
+                            script
+                                .eval(value, &self.stack)
+                                .await
+                                .map_err(|cause| ScriptError {
+                                    script: script_name.to_owned(),
+                                    cause,
+                                })?;
+                        let name = unsafe {
+                            std::str::from_utf8_unchecked(
+                                &name.as_bytes()[..name.len() - (script_name.len() + 1)],
+                            )
+                        };
+                        r.insert(Arc::new(name.to_string()), v);
                     }
+                } else {
+                    r.insert(Arc::new(name.into()), {
+                        let value = xml_util::quot_unescape(value);
+                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(value.as_str())
+                        {
+                            json.into()
+                        } else {
+                            WildDocValue::String(Arc::new(value))
+                        }
+                    });
                 }
             }
         }
 
-        r
+        Ok(r)
+    }
+
+    pub(super) fn required(&self, attr: &Vars, names: &[&Arc<String>]) -> Result<()> {
+        if self.strict {
+            if let Some(name) = names.iter().find(|name| !attr.contains_key(**name)) {
+                return Err(MissingAttributeError(name.to_string()).into());
+            }
+        }
+        Ok(())
     }
 
     fn output_attribute_value(r: &mut Vec<u8>, val: &[u8]) {
@@ -102,8 +119,8 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         &mut self,
         name: &'a str,
         value: &str,
-    ) -> (&'a str, Option<WildDocValue>) {
-        if let Some(script_name) = Self::script_name(name) {
+    ) -> Result<(&'a str, Option<WildDocValue>)> {
+        Ok(if let Some(script_name) = Self::script_name(name) {
             (
                 unsafe {
                     std::str::from_utf8_unchecked(
@@ -111,16 +128,21 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                     )
                 },
                 if let Some(script) = self.scripts.get_mut(script_name) {
-                    script
-                        .eval(&xml_util::quot_unescape(value), &self.stack)
-                        .await
-                        .ok()
+                    Some(
+                        script
+                            .eval(&xml_util::quot_unescape(value), &self.stack)
+                            .await
+                            .map_err(|cause| ScriptError {
+                                script: script_name.to_owned(),
+                                cause,
+                            })?,
+                    )
                 } else {
                     None
                 },
             )
         } else {
             (name, None)
-        }
+        })
     }
 }
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_recursion::async_recursion;
+use hashbrown::HashMap;
+use wild_doc_script::{IncludeAdaptor, Vars};
+
+use crate::r#const::*;
+
+use super::{Element, Node, Output, Parser};
+
+/// Content of a wd:block together with the file it was written in.
+#[derive(Clone)]
+pub(super) struct Block {
+    file: Arc<String>,
+    nodes: Arc<Vec<Node>>,
+}
+
+pub(super) type Blocks = HashMap<Arc<String>, Block>;
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// Renders the layout named by src. The wd:block children override the blocks of the same name in the layout.
+    pub(super) async fn extends(&mut self, element: &Element, r: &mut Output<'_>) -> Result<()> {
+        let attr = self.vars_from_attibutes(&element.attributes).await?;
+        self.required(&attr, &[&*SRC])?;
+
+        let mut blocks = Blocks::new();
+        for block in element
+            .children
+            .iter()
+            .filter_map(|node| node.element())
+            .filter(|element| element.name == "wd:block")
+        {
+            match self
+                .vars_from_attibutes(&block.attributes)
+                .await
+                .and_then(|attr| {
+                    self.required(&attr, &[&*NAME])?;
+                    Ok(attr)
+                }) {
+                Ok(attr) => {
+                    if let Some(name) = attr.get(&*NAME) {
+                        blocks.entry(name.as_string()).or_insert_with(|| Block {
+                            file: self.current_file(),
+                            nodes: Arc::new(block.children.clone()),
+                        });
+                    }
+                }
+                Err(e) => return Err(self.located_error(block, e)),
+            }
+        }
+
+        self.blocks.push(blocks);
+        let ret = self.get_include_content(attr, true, r).await;
+        self.blocks.pop();
+        ret
+    }
+
+    /// Renders the most derived override of the block, or its own content if it is not overridden.
+    pub(super) async fn block(
+        &mut self,
+        attr: Vars,
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let mut chain = vec![];
+        if let Some(name) = attr.get(&*NAME) {
+            let name = name.as_string();
+            chain = self
+                .blocks
+                .iter()
+                .filter_map(|blocks| blocks.get(&name).cloned())
+                .collect();
+        }
+        if chain.is_empty() {
+            self.block_parents.push(chain);
+            let ret = self.parse(nodes, r).await;
+            self.block_parents.pop();
+            ret
+        } else {
+            chain.push(Block {
+                file: self.current_file(),
+                nodes: Arc::new(nodes.to_vec()),
+            });
+            self.render_block(chain, r).await
+        }
+    }
+
+    /// wd:parent renders the block that the current override replaced.
+    pub(super) async fn parent(&mut self, r: &mut Output<'_>) -> Result<()> {
+        match self.block_parents.last() {
+            Some(chain) => {
+                if !chain.is_empty() {
+                    let chain = chain.clone();
+                    self.render_block(chain, r).await?;
+                }
+            }
+            None => {
+                if self.strict {
+                    return Err(anyhow!("used outside of wd:block"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[async_recursion(?Send)]
+    async fn render_block<'w>(&mut self, mut chain: Vec<Block>, r: &mut Output<'w>) -> Result<()> {
+        let block = chain.remove(0);
+        self.block_parents.push(chain);
+        self.include_stack.push(block.file);
+        let ret = self.parse(&block.nodes, r).await;
+        self.include_stack.pop();
+        self.block_parents.pop();
+        ret
+    }
+}
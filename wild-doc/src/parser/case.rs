@@ -1,59 +1,154 @@
+use std::{cmp::Ordering, sync::Arc};
+
 use anyhow::Result;
-use maybe_xml::{token::Ty, Reader};
-use wild_doc_script::{IncludeAdaptor, Vars};
+use hashbrown::HashMap;
+use regex::Regex;
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
 
-use crate::{r#const::*, xml_util};
+use crate::r#const::*;
 
-use super::Parser;
+use super::{Element, Node, Output, Parser};
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
     pub(super) async fn case(
         &mut self,
-        xml: &[u8],
-        pos: &mut usize,
+        nodes: &[Node],
         attr: Vars,
-    ) -> Result<Vec<u8>> {
-        let mut r = None;
-
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         let cmp_src = attr.get(&*VALUE);
-        let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
-        while let Some(token) = reader.tokenize(pos) {
-            match token.ty() {
-                Ty::StartTag(st) => {
-                    let name = st.name();
-                    match name.as_bytes() {
-                        b"wd:when" => {
-                            if let Some(right) =
-                                self.vars_from_attibutes(st.attributes()).await.get(&*VALUE)
-                            {
-                                if let Some(cmp_src) = cmp_src {
-                                    if cmp_src == right {
-                                        r = Some(self.parse(xml, pos).await?);
-                                    }
-                                }
-                            }
-                            if r.is_none() {
-                                xml_util::to_end(xml, pos);
-                            }
+        for element in nodes.iter().filter_map(Node::element) {
+            match element.name.as_str() {
+                "wd:when" => {
+                    if let Some(cmp_src) = cmp_src {
+                        let matched = self
+                            .vars_from_attibutes(&element.attributes)
+                            .await
+                            .and_then(|when| when_matches(cmp_src, &when, &mut self.regexes))
+                            .map_err(|e| self.located_error(element, e))?;
+                        if matched {
+                            return self.parse(&element.children, r).await;
                         }
-                        b"wd:else" => {
-                            if r.is_none() {
-                                r = Some(self.parse(xml, pos).await?)
-                            } else {
-                                xml_util::to_end(xml, pos);
-                            }
-                        }
-                        _ => {}
                     }
                 }
-                Ty::EndTag(t) => {
-                    if t.name().as_str() == "wd:case" {
-                        break;
-                    }
+                "wd:else" => {
+                    return self.parse(&element.children, r).await;
                 }
                 _ => {}
             }
         }
-        Ok(r.unwrap_or_default())
+        Ok(())
+    }
+
+    pub(super) async fn r#if(
+        &mut self,
+        nodes: &[Node],
+        attr: Vars,
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let branches = Self::next_branch(nodes);
+        if is_true(&attr) {
+            return self.parse(&nodes[..branches], r).await;
+        }
+        let mut rest = &nodes[branches..];
+        while let Some((node, tail)) = rest.split_first() {
+            rest = tail;
+            if let Some(element) = node
+                .element()
+                .filter(|element| Self::is_else_branch(element))
+            {
+                // A self-closing branch such as <wd:else /> takes the siblings up to the next branch.
+                let children = if element.empty {
+                    let (children, tail) = rest.split_at(Self::next_branch(rest));
+                    rest = tail;
+                    children
+                } else {
+                    element.children.as_slice()
+                };
+                if element.name == "wd:elseif" {
+                    let attr = self
+                        .vars_from_attibutes(&element.attributes)
+                        .await
+                        .and_then(|attr| {
+                            self.required(&attr, &[&*VALUE])?;
+                            Ok(attr)
+                        })
+                        .map_err(|e| self.located_error(element, e))?;
+                    if !is_true(&attr) {
+                        continue;
+                    }
+                }
+                return self.parse(children, r).await;
+            }
+        }
+        Ok(())
+    }
+
+    fn next_branch(nodes: &[Node]) -> usize {
+        nodes
+            .iter()
+            .position(|node| node.element().is_some_and(Self::is_else_branch))
+            .unwrap_or(nodes.len())
     }
+
+    fn is_else_branch(element: &Element) -> bool {
+        element.name == "wd:elseif" || element.name == "wd:else"
+    }
+}
+
+fn is_true(attr: &Vars) -> bool {
+    attr.get(&*VALUE)
+        .and_then(|v| v.as_bool())
+        .is_some_and(|v| *v)
+}
+
+/// regexes caches the compiled patterns of regex= by their source.
+fn when_matches(
+    value: &WildDocValue,
+    when: &Vars,
+    regexes: &mut HashMap<Arc<String>, Regex>,
+) -> Result<bool> {
+    let mut conditions = 0;
+    let mut matched = true;
+    if let Some(right) = when.get(&*VALUE) {
+        conditions += 1;
+        matched &= value == right;
+    }
+    if let Some(list) = when.get(&*IN) {
+        conditions += 1;
+        let value = value.as_string();
+        matched &= if let WildDocValue::Array(list) = list {
+            list.iter().any(|v| v.as_string() == value)
+        } else {
+            list.as_string()
+                .split(',')
+                .any(|v| v.trim() == value.as_str())
+        };
+    }
+    if let Some(min) = when.get(&*MIN) {
+        conditions += 1;
+        matched &= compare_number(value, min).is_some_and(|o| o != Ordering::Less);
+    }
+    if let Some(max) = when.get(&*MAX) {
+        conditions += 1;
+        matched &= compare_number(value, max).is_some_and(|o| o != Ordering::Greater);
+    }
+    if let Some(pattern) = when.get(&*REGEX) {
+        conditions += 1;
+        let pattern = pattern.as_string();
+        if !regexes.contains_key(&pattern) {
+            let regex = Regex::new(&pattern)?;
+            regexes.insert(Arc::clone(&pattern), regex);
+        }
+        matched &= regexes[&pattern].is_match(&value.as_string());
+    }
+    let not = when
+        .get(&*NOT)
+        .and_then(|v| v.as_bool())
+        .is_some_and(|v| *v);
+    Ok(conditions > 0 && matched != not)
+}
+
+fn compare_number(left: &WildDocValue, right: &WildDocValue) -> Option<Ordering> {
+    left.as_f64()?.partial_cmp(&right.as_f64()?)
 }
@@ -30,12 +30,16 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         r
     }
 
+    // The database guard is held across the async database API; see `Parser::database`.
+    #[allow(clippy::await_holding_lock)]
     pub(super) async fn delete_collection(&self, vars: Vars) {
         if let Some(collection) = vars.get(&*COLLECTION) {
-            self.database
-                .write()
-                .delete_collection(&collection.as_string())
-                .await;
+            let collection = collection.as_string();
+            let collection_id = self.database.read().collection_id(&collection);
+            self.database.write().delete_collection(&collection).await;
+            if let Some(collection_id) = collection_id {
+                self.fulltext.lock().remove_collection(collection_id);
+            }
         }
     }
 }
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use hashbrown::HashMap;
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
+
+use crate::r#const::*;
+
+use super::{Node, Output, Parser};
+
+/// Fragment registered by wd:define.
+pub(super) struct Component {
+    file: Arc<String>,
+    params: Vec<Arc<String>>,
+    nodes: Arc<Vec<Node>>,
+}
+
+pub(super) type Slots = HashMap<Arc<String>, Vec<u8>>;
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    pub(super) fn define(&mut self, attr: Vars, nodes: &[Node]) {
+        if let Some(name) = attr.get(&*NAME) {
+            let params = attr
+                .get(&*PARAMS)
+                .map_or_else(std::vec::Vec::new, |params| {
+                    params
+                        .as_string()
+                        .split(',')
+                        .map(|v| v.trim())
+                        .filter(|v| !v.is_empty())
+                        .map(|v| Arc::new(v.to_owned()))
+                        .collect()
+                });
+            self.components.insert(
+                name.as_string(),
+                Component {
+                    file: self.current_file(),
+                    params,
+                    nodes: Arc::new(nodes.to_vec()),
+                },
+            );
+        }
+    }
+
+    /// Renders the wd:define of the same name with its params as a new scope.
+    /// The content of wd:call is rendered first in the caller's scope and placed at wd:slot.
+    pub(super) async fn call(
+        &mut self,
+        attr: Vars,
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let name = if let Some(name) = attr.get(&*NAME) {
+            name.as_string()
+        } else {
+            return Ok(());
+        };
+        let (file, params, component_nodes) = if let Some(component) = self.components.get(&name) {
+            (
+                Arc::clone(&component.file),
+                component.params.clone(),
+                Arc::clone(&component.nodes),
+            )
+        } else if self.strict {
+            return Err(anyhow!("{} is not defined", name));
+        } else {
+            return Ok(());
+        };
+
+        let mut slots = Slots::new();
+        let mut default_slot = vec![];
+        for node in nodes {
+            if let Some(element) = node.element().filter(|v| v.name == "wd:slot") {
+                let slot_attr = self.vars_from_attibutes(&element.attributes).await?;
+                if let Some(slot_name) = slot_attr.get(&*NAME) {
+                    let content = self.parse_to_vec(&element.children).await?;
+                    slots.insert(slot_name.as_string(), content);
+                    continue;
+                }
+            }
+            default_slot.extend(self.parse_to_vec(std::slice::from_ref(node)).await?);
+        }
+        slots.insert(Arc::clone(&_BLANK), default_slot);
+
+        let mut vars = Vars::new();
+        for param in params {
+            let value = attr.get(&param).cloned().unwrap_or(WildDocValue::Null);
+            vars.insert(param, value);
+        }
+
+        self.check_limits()?;
+        self.check_include_depth(&file)?;
+        self.slots.push(slots);
+        self.stack.push(vars);
+        self.include_stack.push(file);
+        let ret = self.parse(&component_nodes, r).await;
+        self.include_stack.pop();
+        self.stack.pop();
+        self.slots.pop();
+        ret
+    }
+
+    /// Outputs the slot content given to wd:call, or its own content if there is none.
+    /// Content of only whitespace, such as the indent around named slots, counts as none.
+    pub(super) async fn slot(
+        &mut self,
+        attr: Vars,
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        let name = attr
+            .get(&*NAME)
+            .map_or_else(|| Arc::clone(&_BLANK), |v| v.as_string());
+        if let Some(content) = self
+            .slots
+            .last()
+            .and_then(|slots| slots.get(&name))
+            .filter(|v| !v.iter().all(u8::is_ascii_whitespace))
+        {
+            r.extend(content);
+            Ok(())
+        } else {
+            self.parse(nodes, r).await
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
+
+use crate::r#const::*;
+
+use super::{Output, Parser};
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// wd:json outputs value as JSON.
+    /// With collection="...", the rows of search results are expanded to records as wd:record does, limited to fields if given.
+    /// `<`, `>`, `&`, U+2028 and U+2029 are output as \uXXXX so that the JSON can be embedded in a script element.
+    pub(super) fn json(&self, attr: &Vars, r: &mut Output<'_>) -> Result<()> {
+        if let Some(value) = attr.get(&*VALUE) {
+            let value = self.expand_rows(value, attr);
+            let json = serde_json::to_string(&value)?;
+            for c in json.chars() {
+                match c {
+                    '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                        r.extend(format!("\\u{:04X}", c as u32).as_bytes())
+                    }
+                    _ => r.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// wd:csv outputs a search result (expanded by collection) or an array of objects as RFC 4180 CSV.
+    /// fields="name,country" gives the columns, and header="false" omits the header line.
+    pub(super) fn csv(&self, attr: &Vars, r: &mut Output<'_>) -> Result<()> {
+        if !attr.contains_key(&*COLLECTION) && attr.get(&*VALUE).is_some_and(has_search_result) {
+            return Err(anyhow!("collection is required for a search result"));
+        }
+        let columns: Vec<Arc<String>> = field_list(attr)
+            .unwrap_or_default()
+            .iter()
+            .map(|v| v.as_string())
+            .collect();
+        if attr
+            .get(&*HEADER)
+            .and_then(|v| v.as_bool())
+            .is_none_or(|v| *v)
+        {
+            csv_line(columns.iter().map(|v| v.as_str()), r);
+        }
+        if let Some(WildDocValue::Array(rows)) =
+            attr.get(&*VALUE).map(|v| self.expand_rows(v, attr))
+        {
+            for row in rows {
+                let object = match row {
+                    WildDocValue::Object(mut record) if record.contains_key(&*FIELD) => {
+                        record.swap_remove(&*FIELD)
+                    }
+                    row => Some(row),
+                };
+                let cells: Vec<Arc<String>> = columns
+                    .iter()
+                    .map(|column| match &object {
+                        Some(WildDocValue::Object(object)) => match object.get(column) {
+                            None | Some(WildDocValue::Null) => Arc::clone(&_BLANK),
+                            Some(v) => v.as_string(),
+                        },
+                        _ => Arc::clone(&_BLANK),
+                    })
+                    .collect();
+                csv_line(cells.iter().map(|v| v.as_str()), r);
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_rows(&self, value: &WildDocValue, attr: &Vars) -> WildDocValue {
+        match value {
+            WildDocValue::SearchResult(result) => WildDocValue::Array(
+                result
+                    .rows()
+                    .iter()
+                    .map(|row| self.row_record(row.get() as i64, attr))
+                    .collect(),
+            ),
+            WildDocValue::SessionSearchResult(result) => WildDocValue::Array(
+                result
+                    .rows()
+                    .iter()
+                    .map(|row| self.row_record(row.get(), attr))
+                    .collect(),
+            ),
+            WildDocValue::Array(array) => {
+                WildDocValue::Array(array.iter().map(|v| self.expand_rows(v, attr)).collect())
+            }
+            WildDocValue::Object(object) => WildDocValue::Object(
+                object
+                    .iter()
+                    .map(|(k, v)| (Arc::clone(k), self.expand_rows(v, attr)))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    fn row_record(&self, row: i64, attr: &Vars) -> WildDocValue {
+        if let Some(collection) = attr.get(&*COLLECTION) {
+            let mut vars: Vars = [
+                (Arc::clone(&COLLECTION), collection.clone()),
+                (Arc::clone(&ROW), WildDocValue::Number(row.into())),
+                (Arc::clone(&VAR), WildDocValue::String(Arc::clone(&ROW))),
+            ]
+            .into();
+            if let Some(fields) = field_list(attr) {
+                vars.insert(Arc::clone(&FIELDS), WildDocValue::Array(fields));
+            }
+            if let Some((_, record)) = self.record(vars).into_iter().next() {
+                return record;
+            }
+        }
+        WildDocValue::Number(row.into())
+    }
+}
+
+fn has_search_result(value: &WildDocValue) -> bool {
+    match value {
+        WildDocValue::SearchResult(_) | WildDocValue::SessionSearchResult(_) => true,
+        WildDocValue::Array(array) => array.iter().any(has_search_result),
+        WildDocValue::Object(object) => object.values().any(has_search_result),
+        _ => false,
+    }
+}
+
+/// fields is either an array or a comma separated string.
+fn field_list(attr: &Vars) -> Option<Vec<WildDocValue>> {
+    attr.get(&*FIELDS).map(|fields| match fields {
+        WildDocValue::Array(fields) => fields.clone(),
+        _ => fields
+            .as_string()
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| WildDocValue::String(Arc::new(v.to_owned())))
+            .collect(),
+    })
+}
+
+fn csv_line<'a>(cells: impl Iterator<Item = &'a str>, r: &mut Output<'_>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            r.extend(b",");
+        }
+        if cell.contains([',', '"', '\r', '\n']) {
+            r.extend(format!("\"{}\"", cell.replace('"', "\"\"")).as_bytes());
+        } else {
+            r.extend(cell.as_bytes());
+        }
+    }
+    r.extend(b"\r\n");
+}
@@ -0,0 +1,107 @@
+use std::{fmt::Write, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Local};
+use wild_doc_script::WildDocValue;
+
+use crate::xml_util;
+
+/// Applies filters written as `name:argument|name:argument`, from left to right.
+pub(super) fn apply_filters(value: &WildDocValue, filters: &str) -> Result<WildDocValue> {
+    let mut value = value.clone();
+    for filter in filters.split('|') {
+        let (name, arg) = filter
+            .split_once(':')
+            .map_or((filter.trim(), None), |(name, arg)| {
+                (name.trim(), Some(arg))
+            });
+        value = match name {
+            "date" | "date_local" => date(&value, arg, name == "date_local")?,
+            "number" => number(&value, arg)?,
+            "truncate" => truncate(&value, arg)?,
+            "upper" => string(value.as_string().to_uppercase()),
+            "lower" => string(value.as_string().to_lowercase()),
+            "default" => {
+                if value.is_null() {
+                    string(arg.unwrap_or("").to_owned())
+                } else {
+                    value
+                }
+            }
+            "json" => string(serde_json::to_string(&value)?),
+            "base64" => string(match &value {
+                WildDocValue::Binary(v) => general_purpose::STANDARD.encode(v),
+                _ => general_purpose::STANDARD.encode(value.as_string().as_bytes()),
+            }),
+            "urlencode" => string(xml_util::escape_url(&value.as_string())),
+            "" => value,
+            _ => return Err(anyhow!("unknown filter {}", name)),
+        };
+    }
+    Ok(value)
+}
+
+fn string(value: String) -> WildDocValue {
+    WildDocValue::String(Arc::new(value))
+}
+
+/// Formats unix time in seconds. UTC for date, the local time zone for date_local.
+fn date(value: &WildDocValue, format: Option<&str>, local: bool) -> Result<WildDocValue> {
+    if value.is_null() {
+        return Ok(WildDocValue::Null);
+    }
+    let timestamp = value
+        .as_f64()
+        .ok_or_else(|| anyhow!("date requires a number"))?;
+    let date = DateTime::from_timestamp(timestamp as i64, 0)
+        .ok_or_else(|| anyhow!("{} is out of range for date", timestamp))?;
+    let format = format.unwrap_or("%Y-%m-%d %H:%M:%S");
+    let mut r = String::new();
+    if local {
+        write!(r, "{}", date.with_timezone(&Local).format(format))
+    } else {
+        write!(r, "{}", date.format(format))
+    }
+    .map_err(|_| anyhow!("invalid date format {}", format))?;
+    Ok(string(r))
+}
+
+/// Separates thousands with commas and rounds to the given number of decimals (0 by default).
+fn number(value: &WildDocValue, decimals: Option<&str>) -> Result<WildDocValue> {
+    if value.is_null() {
+        return Ok(WildDocValue::Null);
+    }
+    let number = value
+        .as_f64()
+        .ok_or_else(|| anyhow!("number requires a number"))?;
+    let decimals = decimals.map_or(Ok(0), |v| v.trim().parse::<usize>())?;
+
+    let formatted = format!("{:.*}", decimals, number.abs());
+    let (integer, fraction) = formatted.split_at(formatted.find('.').unwrap_or(formatted.len()));
+    let mut r = String::new();
+    if number < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        r.push('-');
+    }
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            r.push(',');
+        }
+        r.push(c);
+    }
+    r.push_str(fraction);
+    Ok(string(r))
+}
+
+/// truncate:length,suffix cuts the string to length characters and appends suffix ("..." by default).
+fn truncate(value: &WildDocValue, arg: Option<&str>) -> Result<WildDocValue> {
+    let arg = arg.ok_or_else(|| anyhow!("truncate requires a length"))?;
+    let (length, suffix) = arg.split_once(',').unwrap_or((arg, "..."));
+    let length = length.trim().parse::<usize>()?;
+    let s = value.as_string();
+    Ok(if s.chars().count() > length {
+        string(s.chars().take(length).collect::<String>() + suffix)
+    } else {
+        value.clone()
+    })
+}
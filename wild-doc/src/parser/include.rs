@@ -3,16 +3,20 @@ use std::{path::Path, sync::Arc};
 use anyhow::Result;
 use wild_doc_script::{IncludeAdaptor, Vars};
 
-use crate::r#const::*;
+use crate::{
+    error::{IncludeCycleError, IncludeDepthError},
+    r#const::*,
+};
 
-use super::Parser;
+use super::{Output, Parser};
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
     pub(super) async fn get_include_content(
         &mut self,
         attr: Vars,
         with_parse: bool,
-    ) -> Result<Vec<u8>> {
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         if let Some(src) = attr.get(&*SRC) {
             let src = src.as_string();
             let (xml, filename) = self
@@ -37,19 +41,55 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                     |xml| (Some(xml), src),
                 );
             if let Some(xml) = xml {
-                if xml.len() > 0 {
-                    return Ok(if with_parse {
-                        self.include_stack.push(filename.into());
-                        let mut pos = 0;
-                        let r = self.parse(xml.as_slice(), &mut pos).await?;
-                        self.include_stack.pop();
-                        r
+                if !xml.is_empty() {
+                    if with_parse {
+                        self.check_limits()?;
+                        self.check_include_cycle(&filename)?;
+                        self.check_include_depth(&filename)?;
+                        self.parse_document(filename, xml, r).await?;
                     } else {
-                        xml.as_ref().clone()
-                    });
+                        r.extend(xml.as_slice());
+                    }
                 }
             }
         }
-        Ok(b"".to_vec())
+        Ok(())
+    }
+
+    /// A file that is already being included would include itself forever.
+    /// Components may call themselves, so wd:call is not checked; the depth limit stops it instead.
+    fn check_include_cycle(&self, filename: &Arc<String>) -> Result<(), IncludeCycleError> {
+        if let Some(i) = self.include_stack.iter().position(|v| v == filename) {
+            return Err(IncludeCycleError(
+                self.include_stack[i..]
+                    .iter()
+                    .chain([filename])
+                    .cloned()
+                    .collect(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The depth counts wd:call as well, since components can call themselves.
+    pub(super) fn check_include_depth(
+        &self,
+        filename: &Arc<String>,
+    ) -> Result<(), IncludeDepthError> {
+        if let Some(max_depth) = self.limits.max_include_depth {
+            if self.include_stack.len() > max_depth {
+                return Err(IncludeDepthError(max_depth, self.include_chain(filename)));
+            }
+        }
+        Ok(())
+    }
+
+    fn include_chain(&self, filename: &Arc<String>) -> Vec<Arc<String>> {
+        self.include_stack
+            .iter()
+            .filter(|v| v.as_str() != "")
+            .chain([filename])
+            .cloned()
+            .collect()
     }
 }
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use wild_doc_script::IncludeAdaptor;
+
+use crate::error::Limit;
+
+use super::Parser;
+
+// Rendering recurses on the stack, so the default stays well within a 2 MiB thread stack.
+const DEFAULT_MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Limits {
+    pub(crate) max_iterations: Option<usize>,
+    pub(crate) time_limit: Option<Duration>,
+    pub(crate) max_include_depth: Option<usize>,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_iterations: None,
+            time_limit: None,
+            max_include_depth: Some(DEFAULT_MAX_INCLUDE_DEPTH),
+        }
+    }
+}
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// Counts one iteration of wd:for / wd:while or one wd:include against the limits of this run.
+    pub(super) fn check_limits(&mut self) -> Result<()> {
+        self.iterations += 1;
+        if let Some(max_iterations) = self.limits.max_iterations {
+            if self.iterations > max_iterations {
+                return Err(Limit::Iterations(max_iterations).into());
+            }
+        }
+        if let Some(time_limit) = self.limits.time_limit {
+            if self.started.elapsed() > time_limit {
+                return Err(Limit::Time(time_limit).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,97 +1,199 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use maybe_xml::token::prop::Attributes;
 use wild_doc_script::{IncludeAdaptor, Vars};
 
-use super::{Parser, WildDocValue};
+use super::{Attribute, Node, Output, Parser, WildDocValue};
 
 use crate::r#const::*;
 
+pub(super) enum LoopControl {
+    Break,
+    Continue,
+}
+
 impl<I: IncludeAdaptor + Send> Parser<I> {
-    pub(super) async fn r#for(&mut self, attr: Vars, xml: &[u8]) -> Result<Vec<u8>> {
-        let mut r = Vec::new();
+    pub(super) async fn r#for(
+        &mut self,
+        attr: Vars,
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         if let (Some(var), Some(r#in)) = (attr.get(&*VAR), attr.get(&*IN)) {
             let var = var.as_string();
             if var.as_str() != "" {
-                match r#in {
-                    WildDocValue::Object(map) => {
-                        if let Some(key_name) = attr.get(&*KEY) {
-                            for (key, value) in map.into_iter() {
-                                let mut new_vars = Vars::new();
-                                new_vars.insert(Arc::clone(&var), value.clone());
-                                new_vars
-                                    .insert(key_name.as_string(), serde_json::json!(key).into());
-                                let mut pos = 0;
-                                self.stack.push(new_vars);
-                                r.extend(self.parse(xml, &mut pos).await?);
-                                self.stack.pop();
-                            }
-                        } else {
-                            for (_, value) in map.into_iter() {
-                                let mut new_vars = Vars::new();
-                                new_vars.insert(Arc::clone(&var), value.clone());
-                                let mut pos = 0;
-                                self.stack.push(new_vars);
-                                r.extend(self.parse(xml, &mut pos).await?);
-                                self.stack.pop();
-                            }
-                        }
+                let offset = self.usize_attr(&attr, &OFFSET)?.unwrap_or(0);
+                let limit = self.usize_attr(&attr, &LIMIT)?;
+                let step = self.usize_attr(&attr, &STEP)?.unwrap_or(1);
+                if step == 0 {
+                    return Err(anyhow!("step must not be 0"));
+                }
+                let items: Vec<(WildDocValue, WildDocValue)> = match r#in {
+                    WildDocValue::Object(map) => select(map.iter(), offset, limit, step)
+                        .into_iter()
+                        .map(|(key, value)| (serde_json::json!(key).into(), value.clone()))
+                        .collect(),
+                    WildDocValue::Array(vec) => select(vec.iter().enumerate(), offset, limit, step)
+                        .into_iter()
+                        .map(|(i, value)| (serde_json::json!(i + 1).into(), value.clone()))
+                        .collect(),
+                    WildDocValue::SearchResult(result) => {
+                        select(result.rows().iter().enumerate(), offset, limit, step)
+                            .into_iter()
+                            .map(|(i, row)| {
+                                (
+                                    serde_json::json!(i + 1).into(),
+                                    WildDocValue::Number(row.get().into()),
+                                )
+                            })
+                            .collect()
+                    }
+                    WildDocValue::SessionSearchResult(result) => {
+                        select(result.rows().iter().enumerate(), offset, limit, step)
+                            .into_iter()
+                            .map(|(i, row)| {
+                                (
+                                    serde_json::json!(i + 1).into(),
+                                    WildDocValue::Number(row.get().into()),
+                                )
+                            })
+                            .collect()
+                    }
+                    _ => vec![],
+                };
+
+                let key_name = attr.get(&*KEY).map(|v| v.as_string());
+                let loop_name = attr.get(&*LOOP).map(|v| v.as_string());
+                let parent = self
+                    .loops
+                    .iter()
+                    .rev()
+                    .find(|v| !v.is_null())
+                    .cloned()
+                    .unwrap_or(WildDocValue::Null);
+                let length = items.len();
+                for (i, (key, value)) in items.into_iter().enumerate() {
+                    let info = loop_info(i, length, &parent);
+                    let mut new_vars = Vars::new();
+                    new_vars.insert(Arc::clone(&var), value);
+                    if let Some(key_name) = &key_name {
+                        new_vars.insert(Arc::clone(key_name), key);
                     }
-                    WildDocValue::Array(vec) => {
-                        let key_name = attr.get(&*KEY);
-                        if let Some(key_name) = key_name {
-                            let mut key = 0;
-                            for value in vec.into_iter() {
-                                key += 1;
-                                let mut new_vars = Vars::new();
-                                new_vars.insert(Arc::clone(&var), value.clone());
-                                new_vars
-                                    .insert(key_name.as_string(), serde_json::json!(key).into());
-                                let mut pos = 0;
-                                self.stack.push(new_vars);
-                                r.extend(self.parse(xml, &mut pos).await?);
-                                self.stack.pop();
-                            }
-                        } else {
-                            for value in vec.into_iter() {
-                                let mut new_vars = Vars::new();
-                                new_vars.insert(Arc::clone(&var), value.clone());
-                                let mut pos = 0;
-                                self.stack.push(new_vars);
-                                r.extend(self.parse(xml, &mut pos).await?);
-                                self.stack.pop();
-                            }
-                        }
+                    if let Some(loop_name) = &loop_name {
+                        new_vars.insert(Arc::clone(loop_name), info.clone());
+                    }
+                    if self.iterate(info, new_vars, nodes, r).await? {
+                        break;
                     }
-                    _ => {}
                 }
             }
         }
-        Ok(r)
+        Ok(())
     }
 
     pub(super) async fn r#while(
         &mut self,
-        attributes: Option<Attributes<'_>>,
-        xml: &[u8],
-    ) -> Result<Vec<u8>> {
-        let mut r = Vec::new();
+        attributes: &[Attribute],
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         loop {
             if self
                 .vars_from_attibutes(attributes)
-                .await
+                .await?
                 .get(&*CONTINUE)
                 .and_then(|v| v.as_bool())
-                .map_or(false, |v| *v)
+                .is_some_and(|v| *v)
             {
-                let mut pos = 0;
-                r.extend(self.parse(xml, &mut pos).await?);
+                if self
+                    .iterate(WildDocValue::Null, Vars::new(), nodes, r)
+                    .await?
+                {
+                    break;
+                }
             } else {
                 break;
             }
         }
-        Ok(r)
+        Ok(())
+    }
+
+    /// Renders one iteration and returns true if wd:break was reached.
+    async fn iterate(
+        &mut self,
+        info: WildDocValue,
+        vars: Vars,
+        nodes: &[Node],
+        r: &mut Output<'_>,
+    ) -> Result<bool> {
+        self.check_limits()?;
+        self.loops.push(info);
+        self.stack.push(vars);
+        let ret = self.parse(nodes, r).await;
+        self.stack.pop();
+        self.loops.pop();
+        ret?;
+        Ok(matches!(self.loop_control.take(), Some(LoopControl::Break)))
     }
+
+    pub(super) fn loop_control(&mut self, control: LoopControl) -> Result<()> {
+        if !self.loops.is_empty() {
+            self.loop_control = Some(control);
+        } else if self.strict {
+            return Err(anyhow!("used outside of wd:for or wd:while"));
+        }
+        Ok(())
+    }
+
+    /// Attribute as a count such as offset or limit. A value that is not one is ignored, or an error in strict mode.
+    fn usize_attr(&self, attr: &Vars, key: &Arc<String>) -> Result<Option<usize>> {
+        match attr.get(key) {
+            None | Some(WildDocValue::Null) => Ok(None),
+            Some(value) => match as_usize(value) {
+                None if self.strict => Err(anyhow!(
+                    "{} must be a non-negative integer: {}",
+                    key,
+                    value.as_string()
+                )),
+                v => Ok(v),
+            },
+        }
+    }
+}
+
+pub(super) fn as_usize(value: &WildDocValue) -> Option<usize> {
+    value.as_string().parse().ok()
+}
+
+fn select<T>(
+    iter: impl Iterator<Item = T>,
+    offset: usize,
+    limit: Option<usize>,
+    step: usize,
+) -> Vec<T> {
+    let iter = iter.skip(offset).step_by(step);
+    if let Some(limit) = limit {
+        iter.take(limit).collect()
+    } else {
+        iter.collect()
+    }
+}
+
+fn loop_info(index0: usize, length: usize, parent: &WildDocValue) -> WildDocValue {
+    // odd and even refer to index1, so the first item is odd.
+    let odd = index0.is_multiple_of(2);
+    WildDocValue::Object(
+        [
+            (Arc::clone(&INDEX0), serde_json::json!(index0).into()),
+            (Arc::clone(&INDEX1), serde_json::json!(index0 + 1).into()),
+            (Arc::clone(&FIRST), WildDocValue::Bool(index0 == 0)),
+            (Arc::clone(&LAST), WildDocValue::Bool(index0 + 1 == length)),
+            (Arc::clone(&LENGTH), serde_json::json!(length).into()),
+            (Arc::clone(&ODD), WildDocValue::Bool(odd)),
+            (Arc::clone(&EVEN), WildDocValue::Bool(!odd)),
+            (Arc::clone(&PARENT), parent.clone()),
+        ]
+        .into(),
+    )
 }
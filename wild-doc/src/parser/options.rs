@@ -0,0 +1,22 @@
+use super::Limits;
+
+/// Settings of a WildDoc that every run is rendered with.
+#[derive(Clone, Copy)]
+pub(crate) struct Options {
+    pub(crate) strict: bool,
+    pub(crate) auto_escape: bool,
+    pub(crate) minify: bool,
+    pub(crate) html5: bool,
+    pub(crate) limits: Limits,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            auto_escape: true,
+            minify: false,
+            html5: false,
+            limits: Limits::default(),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    io::{self, Write},
+    ops::{Deref, DerefMut},
+};
+
+const FLUSH_SIZE: usize = 8192;
+
+/// Rendering destination shared by every level of the parser.
+/// Bytes are appended to a buffer; when a writer is attached the buffer is handed over to it
+/// between nodes, so the document is sent while it is still being rendered.
+pub(crate) struct Output<'w> {
+    buffer: Vec<u8>,
+    writer: Option<&'w mut dyn Write>,
+}
+impl<'w> Output<'w> {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: vec![],
+            writer: None,
+        }
+    }
+
+    pub(crate) fn with_writer(writer: &'w mut dyn Write) -> Self {
+        Self {
+            buffer: Vec::with_capacity(FLUSH_SIZE),
+            writer: Some(writer),
+        }
+    }
+
+    pub(crate) fn write_through(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            if self.buffer.len() >= FLUSH_SIZE {
+                writer.write_all(&self.buffer)?;
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> io::Result<Vec<u8>> {
+        if let Some(writer) = self.writer {
+            writer.write_all(&self.buffer)?;
+            writer.flush()?;
+            Ok(vec![])
+        } else {
+            Ok(self.buffer)
+        }
+    }
+}
+impl Deref for Output<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl DerefMut for Output<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
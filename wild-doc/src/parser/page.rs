@@ -0,0 +1,80 @@
+use std::{num::NonZeroI32, sync::Arc};
+
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
+
+use crate::r#const::*;
+
+use super::{r#loop::as_usize, Parser};
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// With order, offset/limit or page/per_page on the result element, the search result is replaced by
+    /// {rows, len, total, page, pages, offset, limit, result}: rows is the sorted slice, len its length and result is the whole search result.
+    pub(super) fn paginate(
+        &self,
+        result: WildDocValue,
+        attr: &Vars,
+        collection_id: NonZeroI32,
+    ) -> WildDocValue {
+        let order = attr.get(&*ORDER).map(|v| v.as_string());
+        let per_page = attr.get(&*PER_PAGE).and_then(as_usize);
+        let limit = attr.get(&*LIMIT).and_then(as_usize).or(per_page);
+        let offset = attr.get(&*OFFSET).and_then(as_usize).or_else(|| {
+            per_page
+                .zip(attr.get(&*PAGE).and_then(as_usize))
+                .map(|(per_page, page)| per_page * page.saturating_sub(1))
+        });
+        if order.is_none() && limit.is_none() && offset.is_none() {
+            return result;
+        }
+
+        let offset = offset.unwrap_or(0);
+        // Rows after the page are not needed in order.
+        let count = limit.map(|limit| offset.saturating_add(limit));
+        let rows = order
+            .and_then(|order| self.sort_rows(&result, &order, Some(collection_id), count))
+            .unwrap_or_else(|| rows(&result));
+        let total = rows.len();
+        let (page, pages) = match limit {
+            Some(limit) if limit > 0 => (offset / limit + 1, total.div_ceil(limit)),
+            _ => (1, 1),
+        };
+        let rows: Vec<_> = rows
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+
+        WildDocValue::Object(
+            [
+                (Arc::clone(&LEN), WildDocValue::Number(rows.len().into())),
+                (Arc::clone(&ROWS), WildDocValue::Array(rows)),
+                (Arc::clone(&TOTAL), WildDocValue::Number(total.into())),
+                (Arc::clone(&PAGE), WildDocValue::Number(page.into())),
+                (Arc::clone(&PAGES), WildDocValue::Number(pages.into())),
+                (Arc::clone(&OFFSET), WildDocValue::Number(offset.into())),
+                (
+                    Arc::clone(&LIMIT),
+                    limit.map_or(WildDocValue::Null, |v| WildDocValue::Number(v.into())),
+                ),
+                (Arc::clone(&RESULT), result),
+            ]
+            .into(),
+        )
+    }
+}
+
+fn rows(result: &WildDocValue) -> Vec<WildDocValue> {
+    match result {
+        WildDocValue::SearchResult(result) => result
+            .rows()
+            .iter()
+            .map(|row| WildDocValue::Number(row.get().into()))
+            .collect(),
+        WildDocValue::SessionSearchResult(result) => result
+            .rows()
+            .iter()
+            .map(|row| WildDocValue::Number(row.get().into()))
+            .collect(),
+        _ => vec![],
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
+
+use crate::{r#const::*, xml_util};
+
+use super::{filter::apply_filters, Output, Parser};
+
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum Escape {
+    Html,
+    Url,
+    Js,
+    Css,
+    Raw,
+}
+impl Escape {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "html" | "attr" => Some(Self::Html),
+            "url" => Some(Self::Url),
+            "js" => Some(Self::Js),
+            "css" => Some(Self::Css),
+            "raw" => Some(Self::Raw),
+            _ => None,
+        }
+    }
+
+    /// Escape used for wd:print directly inside an HTML element.
+    pub(super) fn for_element(name: &str) -> Option<Self> {
+        match name {
+            "script" => Some(Self::Js),
+            "style" => Some(Self::Css),
+            _ => None,
+        }
+    }
+
+    /// Escape used for a value given to an attribute with a script suffix such as `href:var`.
+    /// URL attributes keep only URLs with safe schemes and event handlers take the value as a JS string.
+    pub(super) fn attribute_value(name: &str, value: &str) -> String {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("on") {
+            xml_util::escape_html(&xml_util::escape_js(value))
+        } else if matches!(
+            name.as_str(),
+            "href"
+                | "src"
+                | "action"
+                | "formaction"
+                | "cite"
+                | "poster"
+                | "background"
+                | "longdesc"
+                | "manifest"
+                | "data"
+                | "xlink:href"
+        ) {
+            xml_util::escape_html(xml_util::allow_url(value))
+        } else {
+            xml_util::escape_html(value)
+        }
+    }
+
+    fn apply(&self, s: &str) -> String {
+        match self {
+            Self::Html => xml_util::escape_html(s),
+            Self::Url => xml_util::escape_url(s),
+            Self::Js => xml_util::escape_js(s),
+            Self::Css => xml_util::escape_css(s),
+            Self::Raw => s.to_owned(),
+        }
+    }
+}
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// wd:print escapes the value for the element it is in unless auto escape is off.
+    /// escape="html|attr|url|js|css|raw" chooses the escape explicitly and raw="true" opts out.
+    /// filter="date:%Y-%m-%d|upper" formats the value before it is escaped.
+    pub(super) fn print(&self, attr: &Vars, r: &mut Output<'_>) -> Result<bool> {
+        if let Some(v) = attr.get(&*VALUE) {
+            let filtered;
+            let v = if let Some(filters) = attr.get(&*FILTER) {
+                filtered = apply_filters(v, &filters.as_string())?;
+                &filtered
+            } else {
+                v
+            };
+            let escape = if attr
+                .get(&*RAW)
+                .and_then(|v| v.as_bool())
+                .is_some_and(|v| *v)
+            {
+                Escape::Raw
+            } else if let Some(name) = attr.get(&*ESCAPE) {
+                let name = name.as_string();
+                Escape::from_name(&name).ok_or_else(|| anyhow!("unknown escape {}", name))?
+            } else if self.auto_escape {
+                self.escape
+            } else {
+                Escape::Raw
+            };
+            match v {
+                WildDocValue::Binary(v) if escape == Escape::Raw => r.extend(v),
+                WildDocValue::Binary(v) => {
+                    r.extend(escape.apply(&String::from_utf8_lossy(v)).as_bytes())
+                }
+                WildDocValue::String(s) if escape == Escape::Raw => r.extend(s.as_bytes()),
+                _ => r.extend(escape.apply(&v.as_string()).as_bytes()),
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
-    num::{NonZeroI32, NonZeroI64},
+    collections::BTreeSet,
+    num::{NonZeroI32, NonZeroI64, NonZeroU32},
     str::FromStr,
     sync::Arc,
 };
@@ -9,18 +10,17 @@ use async_recursion::async_recursion;
 use chrono::DateTime;
 use futures::FutureExt;
 use hashbrown::HashMap;
-use maybe_xml::{token::Ty, Reader};
 use wild_doc_script::{
     search::{self, Search, SearchJoin},
-    Activity, CollectionRow, Condition, IncludeAdaptor, Uuid, Vars, WildDocValue,
+    Activity, CollectionRow, Condition, FieldName, IncludeAdaptor, Uuid, Vars, WildDocValue,
 };
 
-use crate::{r#const::*, xml_util};
+use crate::{fulltext, r#const::*};
 
-use super::Parser;
+use super::{Node, Output, Parser};
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
-    fn collection_id(&self, vars: &Vars) -> Option<NonZeroI32> {
+    pub(super) fn collection_id(&self, vars: &Vars) -> Option<NonZeroI32> {
         if let Some(collection_name) = vars.get(&*COLLECTION) {
             let collection_name = collection_name.as_string();
             if let Some(collection_id) = self.database.read().collection_id(&collection_name) {
@@ -28,7 +28,7 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
             }
             if collection_name.as_str() != "" {
                 if let Some(value) = vars.get(&*CREATE_COLLECTION_IF_NOT_EXISTS) {
-                    if value.as_bool().map_or(false, |v| *v) {
+                    if value.as_bool().is_some_and(|v| *v) {
                         return Some(
                             self.database
                                 .write()
@@ -41,62 +41,61 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         None
     }
 
+    // The database guard is held across the async database API; see `Parser::database`.
+    #[allow(clippy::await_holding_lock)]
     pub(crate) async fn search(
         &mut self,
-        xml: &[u8],
-        pos: &mut usize,
+        nodes: &[Node],
         attr: Vars,
-    ) -> Result<Vec<u8>> {
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         if let Some(collection_id) = self.collection_id(&attr) {
-            let (condition, join, result_info) = self.make_conditions(xml, pos, &attr).await;
+            let (condition, join, result_info) =
+                self.make_conditions(nodes, &attr, collection_id).await?;
             if let Some(result_info) = result_info {
                 let mut new_vars = Vars::new();
                 if let Some(var) = result_info.0.get(&*VAR) {
                     let search = Search::new(collection_id, condition, join);
                     let var = var.as_string();
                     if var.as_str() != "" {
-                        let result = search.result(&*self.database.read()).await;
-                        let mut found_session = false;
+                        let result = search.result(&self.database.read()).await;
+                        let mut value = None;
                         for i in (0..self.sessions.len()).rev() {
                             if let Some(state) = self.sessions.get(i) {
                                 if state.session.temporary_collection(collection_id).is_some() {
-                                    found_session = true;
-                                    let session_result = state.session.result_with(&result).await;
-                                    new_vars.insert(
-                                        Arc::clone(&var),
-                                        WildDocValue::SessionSearchResult(Arc::new(session_result)),
-                                    );
+                                    let session_result =
+                                        Arc::new(state.session.result_with(&result).await);
+                                    self.session_results
+                                        .push((Arc::downgrade(&session_result), collection_id));
+                                    value = Some(WildDocValue::SessionSearchResult(session_result));
                                 }
                             }
                         }
-                        if !found_session {
-                            new_vars.insert(var, WildDocValue::SearchResult(Arc::new(result)));
-                        }
+                        let value =
+                            value.unwrap_or_else(|| WildDocValue::SearchResult(Arc::new(result)));
+                        new_vars.insert(var, self.paginate(value, &result_info.0, collection_id));
                     }
                 }
-                let mut pos = 0;
                 self.stack.push(new_vars);
-                let r = self.parse(result_info.1, &mut pos).await;
+                let ret = self.parse(result_info.1, r).await;
                 self.stack.pop();
-                return r;
+                return ret;
             }
-        } else {
-            xml_util::to_end(xml, pos);
         }
-        Ok(vec![])
+        Ok(())
     }
 
     async fn make_conditions<'a>(
         &mut self,
-        xml: &'a [u8],
-        pos: &mut usize,
+        nodes: &'a [Node],
         attr: &Vars,
-    ) -> (
+        collection_id: NonZeroI32,
+    ) -> Result<(
         Vec<Condition>,
         HashMap<Arc<String>, SearchJoin>,
-        Option<(Vars, &'a [u8])>,
-    ) {
-        let (mut conditions, join, result_info) = self.condition_loop(xml, pos).await;
+        Option<(Vars, &'a [Node])>,
+    )> {
+        let (mut conditions, join, result_info) = self.condition_loop(nodes, collection_id).await?;
 
         if let Some(activity) = attr.get(&*ACTIVITY) {
             conditions.push(Condition::Activity(
@@ -126,103 +125,136 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 }));
             }
         }
-        (conditions, join, result_info)
+        Ok((conditions, join, result_info))
     }
 
     #[async_recursion(?Send)]
     async fn condition_loop<'a>(
         &mut self,
-        xml: &'a [u8],
-        pos: &mut usize,
-    ) -> (
+        nodes: &'a [Node],
+        collection_id: NonZeroI32,
+    ) -> Result<(
         Vec<Condition>,
         HashMap<Arc<String>, SearchJoin>,
-        Option<(Vars, &'a [u8])>,
-    ) {
+        Option<(Vars, &'a [Node])>,
+    )> {
         let mut join = HashMap::new();
         let mut result_conditions = Vec::new();
         let mut result_info = None;
 
         let mut futs = vec![];
 
-        let mut deps = 0;
-        let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
-
-        while let Some(token) = reader.tokenize(pos) {
-            match token.ty() {
-                Ty::StartTag(st) => {
-                    deps += 1;
-                    let name = st.name();
-                    if let None = name.namespace_prefix() {
-                        match name.local().as_bytes() {
-                            b"narrow" => {
-                                if let Ok(inner_xml) = self.parse(xml, pos).await.as_mut() {
-                                    let mut _pos = 0;
-                                    let (cond, _, _) =
-                                        self.condition_loop(&inner_xml, &mut _pos).await;
-                                    result_conditions.push(Condition::Narrow(cond));
-                                }
-                            }
-                            b"wide" => {
-                                if let Ok(inner_xml) = self.parse(xml, pos).await {
-                                    let _pos = 0;
-                                    let (cond, _, _) = self.condition_loop(&inner_xml, pos).await;
-                                    result_conditions.push(Condition::Wide(cond));
-                                }
-                            }
-                            b"join" => {
-                                let attr = self.vars_from_attibutes(st.attributes()).await;
-                                self.join(xml, pos, &attr, &mut join).await;
-                            }
-                            b"result" => {
-                                let attr = self.vars_from_attibutes(st.attributes()).await;
-                                let begin = *pos;
-                                let (inner, _) = xml_util::to_end(xml, pos);
-                                result_info = Some((attr, &xml[begin..inner]));
-                            }
+        for element in nodes.iter().filter_map(Node::element) {
+            if element.empty {
+                let attr = self
+                    .vars_from_attibutes(&element.attributes)
+                    .await
+                    .and_then(|attr| {
+                        match element.local() {
+                            "row" => self.required(&attr, &[&*METHOD, &*VALUE])?,
+                            "field" => self.required(&attr, &[&*NAME, &*METHOD, &*VALUE])?,
+                            "uuid" => self.required(&attr, &[&*VALUE])?,
+                            "depend" => self.required(&attr, &[&*COLLECTION, &*ROW])?,
                             _ => {}
                         }
-                    }
-                }
-                Ty::EmptyElementTag(eet) => {
-                    let attr = self.vars_from_attibutes(eet.attributes()).await;
-                    let name = eet.name();
-                    match name.local().as_bytes() {
-                        b"row" => futs.push(
-                            Self::condition_row(Arc::clone(&METHOD), Arc::clone(&VALUE), attr)
+                        Ok(attr)
+                    })
+                    .map_err(|e| self.located_error(element, e))?;
+                match element.local() {
+                    "row" => futs.push(
+                        Self::condition_row(Arc::clone(&METHOD), Arc::clone(&VALUE), attr)
+                            .boxed_local(),
+                    ),
+                    "field" => {
+                        if attr
+                            .get(&*METHOD)
+                            .is_some_and(|v| v.as_string().as_str() == "fulltext")
+                        {
+                            result_conditions.push(self.condition_fulltext(collection_id, attr));
+                        } else {
+                            futs.push(
+                                Self::condition_field(
+                                    Arc::clone(&NAME),
+                                    Arc::clone(&METHOD),
+                                    Arc::clone(&VALUE),
+                                    attr,
+                                )
                                 .boxed_local(),
-                        ),
-                        b"field" => futs.push(
-                            Self::condition_field(
-                                Arc::clone(&NAME),
-                                Arc::clone(&METHOD),
-                                Arc::clone(&VALUE),
-                                attr,
                             )
-                            .boxed_local(),
-                        ),
-                        b"uuid" => {
-                            futs.push(Self::condition_uuid(Arc::clone(&VALUE), attr).boxed_local())
                         }
-                        b"depend" => {
-                            if let Some(c) = self.condition_depend(attr).await {
-                                result_conditions.push(c);
-                            }
+                    }
+                    "uuid" => {
+                        futs.push(Self::condition_uuid(Arc::clone(&VALUE), attr).boxed_local())
+                    }
+                    "depend" => {
+                        if let Some(c) = self.condition_depend(attr).await {
+                            result_conditions.push(c);
+                        }
+                    }
+                    _ => {
+                        if self.strict {
+                            return Err(self.unknown_tag(element));
                         }
-                        _ => {}
                     }
                 }
-                Ty::EndTag(_) => {
-                    deps -= 1;
-                    if deps <= 0 {
-                        break;
+            } else if element.prefix().is_some() && self.strict {
+                return Err(self.unknown_tag(element));
+            } else {
+                match element.name.as_str() {
+                    "narrow" => {
+                        let inner_xml = self.parse_to_vec(&element.children).await?;
+                        let template = self.compile(inner_xml);
+                        let (cond, _, _) =
+                            self.condition_loop(&template.nodes, collection_id).await?;
+                        result_conditions.push(Condition::Narrow(cond));
+                    }
+                    "wide" => {
+                        let inner_xml = self.parse_to_vec(&element.children).await?;
+                        let template = self.compile(inner_xml);
+                        let (cond, _, _) =
+                            self.condition_loop(&template.nodes, collection_id).await?;
+                        result_conditions.push(Condition::Wide(cond));
+                    }
+                    "join" => {
+                        let attr = self
+                            .vars_from_attibutes(&element.attributes)
+                            .await
+                            .and_then(|attr| {
+                                self.required(&attr, &[&*NAME, &*COLLECTION])?;
+                                Ok(attr)
+                            })
+                            .map_err(|e| self.located_error(element, e))?;
+                        self.join(&element.children, &attr, &mut join).await?;
+                    }
+                    "result" => {
+                        let attr = self
+                            .vars_from_attibutes(&element.attributes)
+                            .await
+                            .and_then(|attr| {
+                                self.required(&attr, &[&*VAR])?;
+                                Ok(attr)
+                            })
+                            .map_err(|e| self.located_error(element, e))?;
+                        result_info = Some((attr, element.children.as_slice()));
+                    }
+                    _ => {
+                        if self.strict {
+                            return Err(self.unknown_tag(element));
+                        }
+                        let (conditions, inner_join, inner_result_info) = self
+                            .condition_loop(&element.children, collection_id)
+                            .await?;
+                        result_conditions.extend(conditions);
+                        join.extend(inner_join);
+                        if inner_result_info.is_some() {
+                            result_info = inner_result_info;
+                        }
                     }
                 }
-                _ => {}
             }
         }
         result_conditions.extend(futures::future::join_all(futs).await.into_iter().flatten());
-        (result_conditions, join, result_info)
+        Ok((result_conditions, join, result_info))
     }
 
     async fn condition_depend(&self, vars: Vars) -> Option<Condition> {
@@ -260,7 +292,7 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 match method.as_str() {
                     "in" => {
                         let v: Vec<_> = value.split(',').flat_map(|s| s.parse::<isize>()).collect();
-                        if v.len() > 0 {
+                        if !v.is_empty() {
                             return Some(Condition::Row(search::Number::In(v)));
                         }
                     }
@@ -299,9 +331,9 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 .then(|| {
                     let v: Vec<_> = value
                         .split(',')
-                        .flat_map(|s| Uuid::from_str(&s).map(|uuid| uuid.as_u128()))
+                        .flat_map(|s| Uuid::from_str(s).map(|uuid| uuid.as_u128()))
                         .collect();
-                    (v.len() > 0).then(|| Condition::Uuid(v))
+                    (!v.is_empty()).then_some(Condition::Uuid(v))
                 })
                 .and_then(|v| v)
         } else {
@@ -333,15 +365,15 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                         "min" => Some(search::Field::Min(value.to_string().into())),
                         "max" => Some(search::Field::Max(value.to_string().into())),
                         "partial" => Some(search::Field::Partial(value)),
-                        "forward" => Some(search::Field::Forward(value.into())),
-                        "backward" => Some(search::Field::Backward(value.into())),
+                        "forward" => Some(search::Field::Forward(value)),
+                        "backward" => Some(search::Field::Backward(value)),
                         "range" => {
                             let s: Vec<_> = value.split("..").collect();
                             (s.len() == 2).then(|| search::Field::Range(s[0].into(), s[1].into()))
                         }
-                        "value_forward" => Some(search::Field::ValueForward(value.into())),
-                        "value_backward" => Some(search::Field::ValueBackward(value.into())),
-                        "value_partial" => Some(search::Field::ValuePartial(value.into())),
+                        "value_forward" => Some(search::Field::ValueForward(value)),
+                        "value_backward" => Some(search::Field::ValueBackward(value)),
+                        "value_partial" => Some(search::Field::ValuePartial(value)),
                         _ => None,
                     }
                     .map(|method| Condition::Field(name, method))
@@ -352,20 +384,63 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         }
     }
 
+    /// Rows whose field contains every word of value, separated by whitespace.
+    /// Committed rows come from the fulltext index, rows of an open session from its temporary data.
+    /// An empty value matches no rows.
+    fn condition_fulltext(&self, collection_id: NonZeroI32, vars: Vars) -> Condition {
+        let words = vars
+            .get(&*VALUE)
+            .map_or(vec![], |value| fulltext::words(&value.as_string()));
+        let mut rows = BTreeSet::new();
+        if let (Some(name), false) = (vars.get(&*NAME), words.is_empty()) {
+            let field = FieldName::new(name.as_string().to_string());
+            let database = self.database.read();
+            if let Some(collection) = database.collection(collection_id) {
+                rows.extend(
+                    self.fulltext
+                        .lock()
+                        .rows(collection, collection_id, &field, &words)
+                        .into_iter()
+                        .map(|row| row.get() as isize),
+                );
+                if let Some(temporary_collection) = self
+                    .sessions
+                    .iter()
+                    .find_map(|state| state.session.temporary_collection(collection_id))
+                {
+                    for (row, entity) in temporary_collection {
+                        let value = match entity.fields().get(&field) {
+                            Some(value) => value.as_slice(),
+                            None if row.get() > 0 => collection
+                                .field_bytes(NonZeroU32::new(row.get() as u32).unwrap(), &field),
+                            None => b"",
+                        };
+                        if fulltext::contains_all(value, &words) {
+                            rows.insert(row.get() as isize);
+                        } else {
+                            rows.remove(&(row.get() as isize));
+                        }
+                    }
+                }
+            }
+        }
+        Condition::Row(search::Number::In(rows.into_iter().collect()))
+    }
+
     async fn join(
         &mut self,
-        xml: &[u8],
-        pos: &mut usize,
+        nodes: &[Node],
         attr: &Vars,
         search_map: &mut HashMap<Arc<String>, SearchJoin>,
-    ) {
+    ) -> Result<()> {
         if let Some(name) = attr.get(&*NAME) {
             let name = name.as_string();
             if name.as_str() != "" {
                 if let Some(collection_id) = self.collection_id(attr) {
                     let relation_key = attr.get(&*RELATION).map(|v| v.as_string());
 
-                    let (conditions, join, _result_info) = self.condition_loop(xml, pos).await;
+                    let (conditions, join, _result_info) =
+                        self.condition_loop(nodes, collection_id).await?;
                     search_map.insert(
                         name,
                         SearchJoin::new(collection_id, conditions, relation_key, join),
@@ -373,5 +448,6 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                 }
             }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use wild_doc_script::{IncludeAdaptor, Vars, WildDocValue};
+
+use crate::r#const::*;
+
+use super::{Node, Parser};
+
+impl<I: IncludeAdaptor + Send> Parser<I> {
+    /// wd:set writes var into the nearest frame that has it, or into the current frame.
+    /// Instead of value, append adds to an array, increment adds to a number and merge adds the keys of an object.
+    pub(super) fn set(&mut self, attr: Vars) -> Result<()> {
+        if let Some(var) = attr.get(&*VAR) {
+            let var = var.as_string();
+            if let Some(current) = self.stack.get_mut(&var) {
+                assign(current, &attr).map_err(|e| anyhow!("{}: {}", var, e))?;
+            } else {
+                let mut value = WildDocValue::Null;
+                assign(&mut value, &attr).map_err(|e| anyhow!("{}: {}", var, e))?;
+                self.set_var(var, value);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_var(&mut self, var: Arc<String>, value: WildDocValue) {
+        if let Some(current) = self.stack.get_mut(&var) {
+            *current = value;
+        } else if let Some(vars) = self.stack.last_mut() {
+            vars.insert(var, value);
+        } else {
+            self.stack.push([(var, value)].into());
+        }
+    }
+
+    /// wd:capture stores the rendered children in var instead of outputting them.
+    pub(super) async fn capture(&mut self, attr: Vars, nodes: &[Node]) -> Result<()> {
+        let rendered = self.parse_to_vec(nodes).await?;
+        if let Some(var) = attr.get(&*VAR) {
+            let value = match String::from_utf8(rendered) {
+                Ok(s) => WildDocValue::String(Arc::new(s)),
+                Err(e) => WildDocValue::Binary(e.into_bytes()),
+            };
+            self.set_var(var.as_string(), value);
+        }
+        Ok(())
+    }
+}
+
+fn assign(current: &mut WildDocValue, attr: &Vars) -> Result<()> {
+    if let Some(value) = attr.get(&*VALUE) {
+        *current = value.clone();
+    } else if let Some(item) = attr.get(&*APPEND) {
+        match &mut *current {
+            WildDocValue::Array(array) => array.push(item.clone()),
+            WildDocValue::Null => *current = WildDocValue::Array(vec![item.clone()]),
+            _ => return Err(anyhow!("{} is not an array", current)),
+        }
+    } else if let Some(increment) = attr.get(&*INCREMENT) {
+        *current = increment_number(current, increment)
+            .ok_or_else(|| anyhow!("{} can not be incremented by {}", current, increment))?;
+    } else if let Some(merge) = attr.get(&*MERGE) {
+        match (&mut *current, merge) {
+            (WildDocValue::Object(object), WildDocValue::Object(merge)) => {
+                for (key, value) in merge {
+                    object.insert(key.clone(), value.clone());
+                }
+            }
+            (WildDocValue::Null, WildDocValue::Object(_)) => *current = merge.clone(),
+            _ => return Err(anyhow!("{} can not be merged with {}", current, merge)),
+        }
+    } else {
+        *current = WildDocValue::Null;
+    }
+    Ok(())
+}
+
+fn increment_number(current: &WildDocValue, increment: &WildDocValue) -> Option<WildDocValue> {
+    let zero = WildDocValue::Number(0.into());
+    let current = if current.is_null() { &zero } else { current };
+    if let (WildDocValue::Number(l), WildDocValue::Number(r)) = (current, increment) {
+        if let Some(v) = l
+            .as_i64()
+            .zip(r.as_i64())
+            .and_then(|(l, r)| l.checked_add(r))
+        {
+            return Some(WildDocValue::Number(v.into()));
+        }
+    }
+    serde_json::Number::from_f64(current.as_f64()? + increment.as_f64()?).map(WildDocValue::Number)
+}
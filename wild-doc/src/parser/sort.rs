@@ -1,64 +1,262 @@
+mod compare;
 mod custom_sort;
 mod custom_sort_session;
+mod field_sort;
 
-use std::sync::Arc;
+pub(crate) use field_sort::collation_key_ja;
 
-use anyhow::Result;
+use std::{
+    num::{NonZeroI32, NonZeroI64, NonZeroU32},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
 use wild_doc_script::{
     CustomOrderKey, FieldName, IncludeAdaptor, Order, SearchResult, SessionOrder, SessionOrderKey,
     SessionSearchResult, Vars, WildDocValue,
 };
 
-use self::{custom_sort::WdCustomSort, custom_sort_session::WdCustomSortSession};
+use self::{
+    compare::{compare, compare_session, sort_prefix, TemporaryRow},
+    custom_sort::WdCustomSort,
+    custom_sort_session::WdCustomSortSession,
+    field_sort::{FieldOrder, FieldSort},
+};
 
-use super::Parser;
+use super::{Node, Output, Parser};
 
 use crate::r#const::*;
 
 impl<I: IncludeAdaptor + Send> Parser<I> {
     pub(crate) async fn sort(
         &mut self,
-        xml: &[u8],
-        pos: &mut usize,
+        nodes: &[Node],
         attr: Vars,
-    ) -> Result<Vec<u8>> {
+        r: &mut Output<'_>,
+    ) -> Result<()> {
         let mut vars = Vars::new();
         if let (Some(WildDocValue::String(order)), Some(result), Some(WildDocValue::String(var))) =
             (attr.get(&*ORDER), attr.get(&*RESULT), attr.get(&*VAR))
         {
             if var.as_str() != "" {
-                match result {
-                    WildDocValue::SearchResult(result) => {
-                        let orders = make_order(result, order);
-                        let rows = result
-                            .sort(&*self.database.read(), &orders)
-                            .into_iter()
-                            .map(|row| WildDocValue::Number(row.get().into()))
-                            .collect();
-                        vars.insert(Arc::clone(var), WildDocValue::Array(rows));
+                let collection_id = self
+                    .collection_id(&attr)
+                    .or_else(|| self.result_collection_id(result));
+                if collection_id.is_none() && self.strict {
+                    if let WildDocValue::SessionSearchResult(_) = result {
+                        if !order.trim().is_empty() {
+                            return Err(anyhow!(
+                                "a session search result needs collection to sort"
+                            ));
+                        }
+                    } else if let Some(key) = order.split(',').find(|o| parse_key(o).2) {
+                        return Err(anyhow!("{} needs collection", key.trim()));
                     }
-                    WildDocValue::SessionSearchResult(result) => {
-                        let _orders = make_order_session(result, order);
-                    }
-                    _ => {}
+                }
+                if let Some(rows) = self.sort_rows(result, order, collection_id, None) {
+                    vars.insert(Arc::clone(var), WildDocValue::Array(rows));
                 }
             }
         }
         self.stack.push(vars);
-        let r = self.parse(xml, pos).await;
+        let ret = self.parse(nodes, r).await;
         self.stack.pop();
-        return r;
+        ret
+    }
+
+    /// Collection of the search result. Session search results are looked up among those made by wd:search.
+    fn result_collection_id(&self, result: &WildDocValue) -> Option<NonZeroI32> {
+        match result {
+            WildDocValue::SearchResult(result) => {
+                result.search().map(|search| search.collection_id())
+            }
+            WildDocValue::SessionSearchResult(result) => self
+                .session_results
+                .iter()
+                .find(|(v, _)| std::ptr::eq(v.as_ptr(), Arc::as_ptr(result)))
+                .map(|(_, collection_id)| *collection_id),
+            _ => None,
+        }
+    }
+
+    /// Rows of the search result sorted by order such as "field.name DESC,row".
+    /// Field keys with NUM, COLLATE or NULLS read the field values of the collection.
+    /// With count, only the first count rows are sorted and the rest follow in any order.
+    /// A session search result is left unsorted without collection.
+    pub(super) fn sort_rows(
+        &self,
+        result: &WildDocValue,
+        order: &str,
+        collection_id: Option<NonZeroI32>,
+        count: Option<usize>,
+    ) -> Option<Vec<WildDocValue>> {
+        match result {
+            WildDocValue::SearchResult(result) => {
+                let orders = make_order(result, order, |field, field_order| {
+                    collection_id.map(|collection_id| {
+                        FieldSort::new(field_order, result.rows().iter().cloned(), |row| {
+                            self.field_bytes(collection_id, row.get().into(), field, false)
+                        })
+                    })
+                });
+                let database = self.database.read();
+                let collection = collection_id.and_then(|id| database.collection(id));
+                let rows = match (collection, count) {
+                    (Some(collection), Some(count))
+                        if !orders.is_empty() && count < result.rows().len() =>
+                    {
+                        let mut rows: Vec<_> = result.rows().iter().cloned().collect();
+                        sort_prefix(&mut rows, count, |a, b| {
+                            compare(collection, &orders, *a, *b)
+                        });
+                        rows
+                    }
+                    _ => result.sort(&database, &orders),
+                };
+                Some(
+                    rows.into_iter()
+                        .map(|row| WildDocValue::Number(row.get().into()))
+                        .collect(),
+                )
+            }
+            WildDocValue::SessionSearchResult(result) => {
+                let orders = make_order_session(result, order, |field, field_order| {
+                    collection_id.map(|collection_id| {
+                        FieldSort::new(field_order, result.rows().iter().cloned(), |row| {
+                            self.field_bytes(collection_id, row.get(), field, true)
+                        })
+                    })
+                });
+                let mut rows: Vec<_> = result.rows().iter().cloned().collect();
+                let rows = if let Some(collection_id) = collection_id {
+                    let database = self.database.read();
+                    let state =
+                        self.sessions.iter().rev().find(|state| {
+                            state.session.temporary_collection(collection_id).is_some()
+                        });
+                    match (database.collection(collection_id), state) {
+                        (Some(collection), Some(state))
+                            if count
+                                .is_none_or(|count| orders.is_empty() || count >= rows.len()) =>
+                        {
+                            state.session.sort(collection, rows, &orders)
+                        }
+                        (Some(collection), state) => {
+                            // Without temporary rows of the collection, the rows are all committed.
+                            let temporary = state.and_then(|state| {
+                                state.session.temporary_collection(collection_id)
+                            });
+                            let temporary = |row| {
+                                temporary
+                                    .and_then(|t| t.get(&row))
+                                    .map(|entity| TemporaryRow {
+                                        term_begin: entity.term_begin(),
+                                        term_end: entity.term_end(),
+                                        fields: entity.fields(),
+                                    })
+                            };
+                            let count = count.unwrap_or(rows.len());
+                            sort_prefix(&mut rows, count, |a, b| {
+                                compare_session(collection, temporary, &orders, *a, *b)
+                            });
+                            rows
+                        }
+                        (None, _) => rows,
+                    }
+                } else {
+                    rows
+                };
+                Some(
+                    rows.into_iter()
+                        .map(|row| WildDocValue::Number(row.get().into()))
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Bytes of the field, from the temporary collection of the sessions first when in_session.
+    pub(super) fn field_bytes(
+        &self,
+        collection_id: NonZeroI32,
+        row: i64,
+        field: &str,
+        in_session: bool,
+    ) -> Option<Vec<u8>> {
+        let field_name = FieldName::new(field.into());
+        if in_session {
+            for state in self.sessions.iter().rev() {
+                if let Some(temporary_collection) =
+                    state.session.temporary_collection(collection_id)
+                {
+                    if let Some(entity) =
+                        NonZeroI64::new(row).and_then(|row| temporary_collection.get(&row))
+                    {
+                        return entity.fields().get(&field_name).map(|v| v.to_vec());
+                    }
+                    break;
+                }
+            }
+        }
+        let row = NonZeroU32::new(u32::try_from(row).ok()?)?;
+        self.database
+            .read()
+            .collection(collection_id)
+            .map(|collection| collection.field_bytes(row, &field_name).to_vec())
     }
 }
 
-fn make_order(result: &Arc<SearchResult>, sort: &str) -> Vec<Order<WdCustomSort>> {
+/// Splits a key of order such as "field.price NUM DESC NULLS LAST" into the key and its modifiers.
+/// The bool is true when NUM, COLLATE or NULLS is given.
+fn parse_key(o: &str) -> (&str, FieldOrder, bool) {
+    let mut words = o.split_whitespace();
+    let key = words.next().unwrap_or("");
+    let mut field_order = FieldOrder::default();
+    let mut typed = false;
+    while let Some(word) = words.next() {
+        match word {
+            "DESC" => field_order.desc = true,
+            "ASC" => field_order.desc = false,
+            "NUM" => {
+                field_order.numeric = true;
+                typed = true;
+            }
+            "COLLATE" => {
+                field_order.collate_ja = words.next() == Some("ja");
+                typed = true;
+            }
+            "NULLS" => {
+                field_order.nulls_last = words.next().map(|v| v == "LAST");
+                typed = true;
+            }
+            _ => {}
+        }
+    }
+    (key, field_order, typed)
+}
+
+fn make_order(
+    result: &Arc<SearchResult>,
+    sort: &str,
+    field_sort: impl Fn(&str, FieldOrder) -> Option<FieldSort<NonZeroU32>>,
+) -> Vec<Order<WdCustomSort>> {
     let mut orders = vec![];
-    if sort.len() > 0 {
+    if !sort.is_empty() {
         for o in sort.trim().split(",") {
-            let o = o.trim();
-            let is_desc = o.ends_with(" DESC");
-            let o_split: Vec<_> = o.split(" ").collect();
-            let field = o_split[0];
+            let (field, field_order, typed) = parse_key(o);
+            if typed {
+                if let Some(sort) = field
+                    .strip_prefix("field.")
+                    .and_then(|v| field_sort(v, field_order))
+                {
+                    orders.push(Order::Asc(CustomOrderKey::Custom(WdCustomSort::Field(
+                        sort,
+                    ))));
+                    continue;
+                }
+            }
+            let is_desc = field_order.desc;
             if let Some(order_key) = if field.starts_with("field.") {
                 field
                     .strip_prefix("field.")
@@ -68,7 +266,7 @@ fn make_order(result: &Arc<SearchResult>, sort: &str) -> Vec<Order<WdCustomSort>
                     .strip_prefix("join.")
                     .map(|v| -> CustomOrderKey<WdCustomSort> {
                         let s: Vec<_> = v.split(".").collect();
-                        CustomOrderKey::Custom(WdCustomSort {
+                        CustomOrderKey::Custom(WdCustomSort::Join {
                             result: Arc::clone(result),
                             join_name: s[0].into(),
                             property: s[1].into(),
@@ -98,14 +296,24 @@ fn make_order(result: &Arc<SearchResult>, sort: &str) -> Vec<Order<WdCustomSort>
 fn make_order_session(
     result: &Arc<SessionSearchResult>,
     sort: &str,
+    field_sort: impl Fn(&str, FieldOrder) -> Option<FieldSort<NonZeroI64>>,
 ) -> Vec<SessionOrder<WdCustomSortSession>> {
     let mut orders = vec![];
-    if sort.len() > 0 {
+    if !sort.is_empty() {
         for o in sort.trim().split(",") {
-            let o = o.trim();
-            let is_desc = o.ends_with(" DESC");
-            let o_split: Vec<_> = o.split(" ").collect();
-            let field = o_split[0];
+            let (field, field_order, typed) = parse_key(o);
+            if typed {
+                if let Some(sort) = field
+                    .strip_prefix("field.")
+                    .and_then(|v| field_sort(v, field_order))
+                {
+                    orders.push(SessionOrder::Asc(SessionOrderKey::Custom(
+                        WdCustomSortSession::Field(sort),
+                    )));
+                    continue;
+                }
+            }
+            let is_desc = field_order.desc;
             if let Some(order_key) = if field.starts_with("field.") {
                 field
                     .strip_prefix("field.")
@@ -115,7 +323,7 @@ fn make_order_session(
                     .strip_prefix("join.")
                     .map(|v| -> SessionOrderKey<WdCustomSortSession> {
                         let s: Vec<_> = v.split(".").collect();
-                        SessionOrderKey::Custom(WdCustomSortSession {
+                        SessionOrderKey::Custom(WdCustomSortSession::Join {
                             result: Arc::clone(result),
                             join_name: s[0].into(),
                             property: s[1].into(),
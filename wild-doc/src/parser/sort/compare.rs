@@ -0,0 +1,113 @@
+use std::{
+    cmp::Ordering,
+    num::{NonZeroI64, NonZeroU32},
+};
+
+use hashbrown::HashMap;
+use idx_binary::{AvltrieeSearch, IdxBinary};
+use wild_doc_script::{
+    Collection, CustomOrderKey, CustomSort, FieldName, Order, SessionCustomOrder, SessionOrder,
+    SessionOrderKey,
+};
+
+/// A row of a session that is not in the collection yet.
+pub(super) struct TemporaryRow<'a> {
+    pub(super) term_begin: u64,
+    pub(super) term_end: u64,
+    pub(super) fields: &'a HashMap<FieldName, Vec<u8>>,
+}
+
+/// Sorts only the first count rows, which is all a page needs. The rest are left in any order.
+pub(super) fn sort_prefix<R>(rows: &mut [R], count: usize, compare: impl Fn(&R, &R) -> Ordering) {
+    if count == 0 {
+        return;
+    }
+    if count < rows.len() {
+        rows.select_nth_unstable_by(count - 1, &compare);
+    }
+    let count = count.min(rows.len());
+    rows[..count].sort_by(compare);
+}
+
+/// Compares rows as the sort of the collection does. Rows equal in every key keep row order.
+pub(super) fn compare<C: CustomSort>(
+    collection: &Collection,
+    orders: &[Order<C>],
+    a: NonZeroU32,
+    b: NonZeroU32,
+) -> Ordering {
+    for order in orders {
+        let (key, desc) = match order {
+            Order::Asc(key) => (key, false),
+            Order::Desc(key) => (key, true),
+        };
+        let ordering = match key {
+            CustomOrderKey::Serial => collection.serial(a).cmp(collection.serial(b)),
+            CustomOrderKey::Row => a.cmp(&b),
+            CustomOrderKey::TermBegin => collection.term_begin(a).cmp(&collection.term_begin(b)),
+            CustomOrderKey::TermEnd => collection.term_end(a).cmp(&collection.term_end(b)),
+            CustomOrderKey::LastUpdated => {
+                collection.last_updated(a).cmp(&collection.last_updated(b))
+            }
+            CustomOrderKey::Field(name) => IdxBinary::cmp(
+                collection.field_bytes(a, name),
+                collection.field_bytes(b, name),
+            ),
+            CustomOrderKey::Custom(custom) => custom.compare(a, b),
+        };
+        if ordering != Ordering::Equal {
+            return if desc { ordering.reverse() } else { ordering };
+        }
+    }
+    a.cmp(&b)
+}
+
+/// Compares rows as Session::sort does. Negative rows are read from the temporary rows of the session.
+pub(super) fn compare_session<'a, C: SessionCustomOrder>(
+    collection: &'a Collection,
+    temporary: impl Fn(NonZeroI64) -> Option<TemporaryRow<'a>>,
+    orders: &[SessionOrder<C>],
+    a: NonZeroI64,
+    b: NonZeroI64,
+) -> Ordering {
+    let committed = |row: NonZeroI64| NonZeroU32::try_from(row).ok();
+    let serial = |row| committed(row).map_or(0, |row| *collection.serial(row));
+    let last_updated = |row| {
+        committed(row)
+            .and_then(|row| collection.last_updated(row))
+            .map_or(0, |v| *v)
+    };
+    let term_begin = |row| match committed(row) {
+        Some(row) => collection.term_begin(row).map_or(0, |v| *v),
+        None => temporary(row).map_or(0, |row| row.term_begin),
+    };
+    let term_end = |row| match committed(row) {
+        Some(row) => collection.term_end(row).map_or(0, |v| *v),
+        None => temporary(row).map_or(0, |row| row.term_end),
+    };
+    let field = |row, name| match committed(row) {
+        Some(row) => collection.field_bytes(row, name),
+        None => temporary(row)
+            .and_then(|row| row.fields.get(name))
+            .map_or(b"".as_slice(), |v| v.as_slice()),
+    };
+    for order in orders {
+        let (key, desc) = match order {
+            SessionOrder::Asc(key) => (key, false),
+            SessionOrder::Desc(key) => (key, true),
+        };
+        let ordering = match key {
+            SessionOrderKey::Serial => serial(a).cmp(&serial(b)),
+            SessionOrderKey::Row => a.cmp(&b),
+            SessionOrderKey::TermBegin => term_begin(a).cmp(&term_begin(b)),
+            SessionOrderKey::TermEnd => term_end(a).cmp(&term_end(b)),
+            SessionOrderKey::LastUpdated => last_updated(a).cmp(&last_updated(b)),
+            SessionOrderKey::Field(name) => IdxBinary::cmp(field(a, name), field(b, name)),
+            SessionOrderKey::Custom(custom) => custom.compare(a, b),
+        };
+        if ordering != Ordering::Equal {
+            return if desc { ordering.reverse() } else { ordering };
+        }
+    }
+    a.cmp(&b)
+}
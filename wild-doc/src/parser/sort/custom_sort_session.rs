@@ -2,62 +2,86 @@ use std::{cmp::Ordering, num::NonZeroI64, sync::Arc};
 
 use wild_doc_script::{SessionCustomOrder, SessionSearchResult};
 
-pub struct WdCustomSortSession {
-    pub(super) result: Arc<SessionSearchResult>,
-    pub(super) join_name: String,
-    pub(super) property: String,
+use super::field_sort::FieldSort;
+
+pub enum WdCustomSortSession {
+    Join {
+        result: Arc<SessionSearchResult>,
+        join_name: String,
+        property: String,
+    },
+    Field(FieldSort<NonZeroI64>),
 }
 
 impl SessionCustomOrder for WdCustomSortSession {
     fn compare(&self, a: NonZeroI64, b: NonZeroI64) -> std::cmp::Ordering {
-        if let Some(join) = self.result.as_ref().join().get(&self.join_name) {
-            match self.property.as_str() {
-                "len" => {
-                    if let (Some(a), Some(b)) = (join.get(&a), join.get(&b)) {
-                        return a.rows().len().cmp(&b.rows().len());
-                    }
+        let (result, join_name, property) = match self {
+            Self::Join {
+                result,
+                join_name,
+                property,
+            } => (result, join_name, property),
+            Self::Field(sort) => return sort.compare(a, b),
+        };
+        if let Some(join) = result.as_ref().join().get(join_name) {
+            if property.as_str() == "len" {
+                if let (Some(a), Some(b)) = (join.get(&a), join.get(&b)) {
+                    return a.rows().len().cmp(&b.rows().len());
                 }
-                _ => {}
             }
         }
         Ordering::Equal
     }
 
     fn asc(&self) -> Vec<NonZeroI64> {
-        if let Some(join) = self.result.join().get(&self.join_name) {
-            match self.property.as_str() {
-                "len" => {
-                    let mut sorted: Vec<_> = self.result.rows().into_iter().cloned().collect();
-                    sorted.sort_by(|a, b| {
-                        if let (Some(a), Some(b)) = (join.get(a), join.get(b)) {
-                            a.rows().len().cmp(&b.rows().len())
-                        } else {
-                            Ordering::Equal
-                        }
-                    });
-                    return sorted;
-                }
-                _ => {}
+        let (result, join_name, property) = match self {
+            Self::Join {
+                result,
+                join_name,
+                property,
+            } => (result, join_name, property),
+            Self::Field(sort) => return sort.sorted(),
+        };
+        if let Some(join) = result.join().get(join_name) {
+            if property.as_str() == "len" {
+                let mut sorted: Vec<_> = result.rows().iter().cloned().collect();
+                sorted.sort_by(|a, b| {
+                    if let (Some(a), Some(b)) = (join.get(a), join.get(b)) {
+                        a.rows().len().cmp(&b.rows().len())
+                    } else {
+                        Ordering::Equal
+                    }
+                });
+                return sorted;
             }
         }
         vec![]
     }
 
     fn desc(&self) -> Vec<NonZeroI64> {
-        if let Some(join) = self.result.join().get(&self.join_name) {
-            match self.property.as_str() {
-                "len" => {
-                    let mut sorted: Vec<_> = self.result.rows().into_iter().cloned().collect();
-                    sorted.sort_by(|a, b| {
-                        if let (Some(a), Some(b)) = (join.get(a), join.get(b)) {
-                            b.rows().len().cmp(&a.rows().len())
-                        } else {
-                            Ordering::Equal
-                        }
-                    });
-                    return sorted;
-                }
-                _ => {}
+        let (result, join_name, property) = match self {
+            Self::Join {
+                result,
+                join_name,
+                property,
+            } => (result, join_name, property),
+            Self::Field(sort) => {
+                let mut rows = sort.sorted();
+                rows.reverse();
+                return rows;
+            }
+        };
+        if let Some(join) = result.join().get(join_name) {
+            if property.as_str() == "len" {
+                let mut sorted: Vec<_> = result.rows().iter().cloned().collect();
+                sorted.sort_by(|a, b| {
+                    if let (Some(a), Some(b)) = (join.get(a), join.get(b)) {
+                        b.rows().len().cmp(&a.rows().len())
+                    } else {
+                        Ordering::Equal
+                    }
+                });
+                return sorted;
             }
         }
         vec![]
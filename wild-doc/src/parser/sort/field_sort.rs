@@ -0,0 +1,119 @@
+use std::{cmp::Ordering, hash::Hash};
+
+use hashbrown::HashMap;
+
+/// Modifiers of a field key: `NUM`, `COLLATE ja`, `NULLS FIRST|LAST` and the direction.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FieldOrder {
+    pub(crate) numeric: bool,
+    pub(crate) collate_ja: bool,
+    pub(crate) nulls_last: Option<bool>,
+    pub(crate) desc: bool,
+}
+
+enum SortValue {
+    Number(f64),
+    Text(String),
+}
+
+/// Rows ordered by the values of a field read in advance.
+/// The direction and the place of nulls are part of the comparison, so it is used as an ascending key.
+pub struct FieldSort<R> {
+    rows: Vec<R>,
+    values: HashMap<R, Option<SortValue>>,
+    order: FieldOrder,
+}
+
+impl<R: Copy + Eq + Hash> FieldSort<R> {
+    pub(crate) fn new(
+        order: FieldOrder,
+        rows: impl Iterator<Item = R>,
+        bytes: impl Fn(R) -> Option<Vec<u8>>,
+    ) -> Self {
+        let rows: Vec<R> = rows.collect();
+        let values = rows
+            .iter()
+            .map(|row| (*row, bytes(*row).and_then(|v| order.value(&v))))
+            .collect();
+        Self {
+            rows,
+            values,
+            order,
+        }
+    }
+
+    pub(crate) fn compare(&self, a: R, b: R) -> Ordering {
+        let a = self.values.get(&a).and_then(|v| v.as_ref());
+        let b = self.values.get(&b).and_then(|v| v.as_ref());
+        // Without NULLS, null is the smallest value as in byte order.
+        let nulls_last = self.order.nulls_last.unwrap_or(self.order.desc);
+        match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => {
+                if nulls_last {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                }
+            }
+            (Some(_), None) => {
+                if nulls_last {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+            (Some(a), Some(b)) => {
+                let ordering = match (a, b) {
+                    (SortValue::Number(a), SortValue::Number(b)) => {
+                        a.partial_cmp(b).unwrap_or(Ordering::Equal)
+                    }
+                    (SortValue::Text(a), SortValue::Text(b)) => a.cmp(b),
+                    (SortValue::Number(_), SortValue::Text(_)) => Ordering::Less,
+                    (SortValue::Text(_), SortValue::Number(_)) => Ordering::Greater,
+                };
+                if self.order.desc {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+        }
+    }
+
+    pub(crate) fn sorted(&self) -> Vec<R> {
+        let mut rows = self.rows.clone();
+        rows.sort_by(|a, b| self.compare(*a, *b));
+        rows
+    }
+}
+
+impl FieldOrder {
+    /// Empty values, and with NUM values that are not numbers, are null.
+    fn value(&self, bytes: &[u8]) -> Option<SortValue> {
+        let s = String::from_utf8_lossy(bytes);
+        if self.numeric {
+            s.trim().parse().ok().map(SortValue::Number)
+        } else if s.is_empty() {
+            None
+        } else if self.collate_ja {
+            Some(SortValue::Text(collation_key_ja(&s)))
+        } else {
+            Some(SortValue::Text(s.into_owned()))
+        }
+    }
+}
+
+/// Japanese text compares full-width alphanumerics as half-width, katakana as hiragana and ignores case,
+/// so kana sort in gojuon order. Kanji keep code point order since their readings are unknown.
+pub(crate) fn collation_key_ja(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,596 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    sync::Arc,
+};
+
+use hashbrown::HashMap;
+use maybe_xml::{token::Ty, Reader};
+
+use crate::xml_util;
+
+/// Documents given to run are kept up to this many; when full, they are all dropped.
+const MAX_DOCUMENTS: usize = 64;
+
+/// Compiled templates, of included files by filename and of documents given to run by the hash of their content.
+#[derive(Default)]
+pub struct TemplateCache {
+    files: HashMap<Arc<String>, Arc<Template>>,
+    documents: HashMap<u64, Arc<Template>>,
+}
+impl TemplateCache {
+    pub(crate) fn file(
+        &mut self,
+        filename: &Arc<String>,
+        xml: Arc<Vec<u8>>,
+        minify: bool,
+        html5: bool,
+    ) -> Arc<Template> {
+        if let Some(template) = self.files.get(filename) {
+            if Arc::ptr_eq(&template.source, &xml) || template.source == xml {
+                return Arc::clone(template);
+            }
+        }
+        let template = Arc::new(Template::with_options(xml, minify, html5));
+        self.files
+            .insert(Arc::clone(filename), Arc::clone(&template));
+        template
+    }
+
+    pub(crate) fn document(
+        &mut self,
+        xml: Arc<Vec<u8>>,
+        minify: bool,
+        html5: bool,
+    ) -> Arc<Template> {
+        let mut hasher = DefaultHasher::new();
+        xml.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some(template) = self.documents.get(&hash) {
+            if template.source == xml {
+                return Arc::clone(template);
+            }
+        }
+        if self.documents.len() >= MAX_DOCUMENTS {
+            self.documents.clear();
+        }
+        let template = Arc::new(Template::with_options(xml, minify, html5));
+        self.documents.insert(hash, Arc::clone(&template));
+        template
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.files.clear();
+        self.documents.clear();
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Attribute {
+    pub(crate) name: String,
+    pub(crate) value: Option<String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<Attribute>,
+    pub(crate) children: Vec<Node>,
+    pub(crate) empty: bool,
+    pub(crate) inner: Range<usize>,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+impl Element {
+    pub(crate) fn is_wd(&self) -> bool {
+        self.name.starts_with("wd:")
+    }
+
+    pub(crate) fn local(&self) -> &str {
+        self.name
+            .split_once(':')
+            .map_or(self.name.as_str(), |(_, local)| local)
+    }
+
+    pub(crate) fn prefix(&self) -> Option<&str> {
+        self.name.split_once(':').map(|(prefix, _)| prefix)
+    }
+
+    /// Writes the element back as markup. wd:comment, wd:letitgo and wd:trim have already been applied.
+    pub(crate) fn write_source(&self, html5: bool, r: &mut Vec<u8>) {
+        r.push(b'<');
+        r.extend(self.name.as_bytes());
+        for attr in self.attributes.iter() {
+            r.push(b' ');
+            r.extend(attr.name.as_bytes());
+            if let Some(value) = &attr.value {
+                r.extend(b"=\"");
+                r.extend(xml_util::escape_html(&xml_util::unescape(value)).as_bytes());
+                r.push(b'"');
+            }
+        }
+        if self.empty {
+            r.extend(b" />");
+        } else if is_void_element(&self.name, html5) {
+            r.push(b'>');
+        } else {
+            r.push(b'>');
+            for child in self.children.iter() {
+                match child {
+                    Node::Text(text) => r.extend(text),
+                    Node::ProcessingInstruction {
+                        target,
+                        instructions,
+                        ..
+                    } => {
+                        r.extend(b"<?");
+                        r.extend(target.as_bytes());
+                        r.push(b' ');
+                        r.extend(instructions.as_bytes());
+                        r.extend(b"?>");
+                    }
+                    Node::Element(element) => element.write_source(html5, r),
+                }
+            }
+            r.extend(b"</");
+            r.extend(self.name.as_bytes());
+            r.push(b'>');
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum Node {
+    Text(Vec<u8>),
+    ProcessingInstruction {
+        target: String,
+        instructions: String,
+        line: usize,
+        column: usize,
+    },
+    Element(Element),
+}
+
+impl Node {
+    pub(crate) fn element(&self) -> Option<&Element> {
+        if let Node::Element(element) = self {
+            Some(element)
+        } else {
+            None
+        }
+    }
+}
+
+/// Elements written without an end tag. With html5, the HTML5 void elements in any case.
+pub(crate) fn is_void_element(name: &str, html5: bool) -> bool {
+    if html5 {
+        matches!(
+            name.to_ascii_lowercase().as_str(),
+            "area"
+                | "base"
+                | "br"
+                | "col"
+                | "embed"
+                | "hr"
+                | "img"
+                | "input"
+                | "link"
+                | "meta"
+                | "param"
+                | "source"
+                | "track"
+                | "wbr"
+        )
+    } else {
+        matches!(name, "input" | "br" | "hr")
+    }
+}
+
+/// Elements whose content is text. Only wd: tags and the end tag are read in them.
+fn is_raw_text_element(name: &str) -> bool {
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "script" | "style" | "textarea"
+    )
+}
+
+/// Position of the next wd: tag or the end tag of the raw text element.
+fn raw_text_end(xml: &[u8], from: usize, name: &str) -> usize {
+    let mut i = from;
+    while let Some(p) = xml[i..].iter().position(|c| *c == b'<') {
+        let rest = &xml[i + p + 1..];
+        if rest.starts_with(b"wd:")
+            || rest.starts_with(b"/wd:")
+            || (rest.starts_with(b"/")
+                && rest.len() > name.len()
+                && rest[1..=name.len()].eq_ignore_ascii_case(name.as_bytes()))
+        {
+            return i + p;
+        }
+        i += p + 1;
+    }
+    xml.len()
+}
+
+struct LineCounter {
+    pos: usize,
+    line: usize,
+    line_begin: usize,
+}
+impl LineCounter {
+    fn position(&mut self, xml: &[u8], pos: usize) -> (usize, usize) {
+        for (i, c) in xml[self.pos..pos].iter().enumerate() {
+            if *c == b'\n' {
+                self.line += 1;
+                self.line_begin = self.pos + i + 1;
+            }
+        }
+        self.pos = pos;
+        let line_bytes = &xml[self.line_begin..pos];
+        (
+            self.line,
+            std::str::from_utf8(line_bytes).map_or(line_bytes.len(), |s| s.chars().count()) + 1,
+        )
+    }
+}
+
+/// A compiled document, as returned by `WildDoc::compile`.
+pub struct Template {
+    pub(crate) source: Arc<Vec<u8>>,
+    pub(crate) nodes: Vec<Node>,
+}
+impl Template {
+    pub(crate) fn with_options(source: Arc<Vec<u8>>, minify: bool, html5: bool) -> Self {
+        let nodes = Self::compile(&source, minify, html5);
+        Self { source, nodes }
+    }
+
+    fn siblings<'a>(open: &'a mut [Element], nodes: &'a mut Vec<Node>) -> &'a mut Vec<Node> {
+        if let Some(parent) = open.last_mut() {
+            &mut parent.children
+        } else {
+            nodes
+        }
+    }
+
+    fn push(open: &mut [Element], nodes: &mut Vec<Node>, node: Node) {
+        let nodes = Self::siblings(open, nodes);
+        if let Node::Text(text) = &node {
+            if let Some(Node::Text(last)) = nodes.last_mut() {
+                last.extend(text);
+                return;
+            }
+        }
+        nodes.push(node);
+    }
+
+    fn close(
+        xml: &[u8],
+        open: &mut Vec<Element>,
+        trims: &mut Vec<Trim>,
+        nodes: &mut Vec<Node>,
+        end: usize,
+    ) -> Trim {
+        let trim = trims.pop().unwrap_or_default();
+        if let Some(mut element) = open.pop() {
+            element.inner.end = end;
+            if trim.inner {
+                if let Some(Node::Text(text)) = element.children.first_mut() {
+                    *text = trim_start(text).to_vec();
+                }
+                if let Some(Node::Text(text)) = element.children.last_mut() {
+                    *text = trim_end(text).to_vec();
+                }
+            }
+            match element.name.as_str() {
+                "wd:comment" => {}
+                "wd:letitgo" => {
+                    Self::push(open, nodes, Node::Text(xml[element.inner.clone()].to_vec()));
+                }
+                _ => {
+                    Self::push(open, nodes, Node::Element(element));
+                }
+            }
+        }
+        trim
+    }
+
+    /// Removes the wd:trim attribute and trims the text before the element if asked.
+    fn take_trim(
+        attributes: &mut Vec<Attribute>,
+        open: &mut [Element],
+        nodes: &mut Vec<Node>,
+    ) -> Trim {
+        let trim = attributes
+            .iter()
+            .position(|attr| attr.name == "wd:trim")
+            .map_or_else(Trim::default, |i| {
+                Trim::parse(attributes.remove(i).value.as_deref().unwrap_or(""))
+            });
+        if trim.before {
+            let siblings = Self::siblings(open, nodes);
+            if let Some(Node::Text(text)) = siblings.last_mut() {
+                *text = trim_end(text).to_vec();
+                if text.is_empty() {
+                    siblings.pop();
+                }
+            }
+        }
+        trim
+    }
+
+    fn compile(xml: &[u8], minify: bool, html5: bool) -> Vec<Node> {
+        let mut nodes = vec![];
+        let mut open: Vec<Element> = vec![];
+        let mut trims: Vec<Trim> = vec![];
+        let mut trim_next = false;
+        let mut counter = LineCounter {
+            pos: 0,
+            line: 1,
+            line_begin: 0,
+        };
+
+        let mut pos = 0;
+        let mut pos_before = 0;
+        let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
+        loop {
+            if let Some(end) = open
+                .iter()
+                .find(|e| html5 && is_raw_text_element(&e.name))
+                .map(|e| raw_text_end(xml, pos, &e.name))
+            {
+                if end > pos {
+                    Self::push(&mut open, &mut nodes, Node::Text(xml[pos..end].to_vec()));
+                    pos = end;
+                    pos_before = end;
+                }
+            }
+            let token = if let Some(token) = reader.tokenize(&mut pos) {
+                token
+            } else {
+                break;
+            };
+            let trim_start_of_text = std::mem::take(&mut trim_next);
+            match token.ty() {
+                Ty::StartTag(st) => {
+                    let (line, column) = counter.position(xml, pos_before);
+                    let mut element = Element {
+                        name: st.name().as_str().to_owned(),
+                        attributes: st.attributes().map_or(vec![], |attributes| {
+                            attributes
+                                .into_iter()
+                                .map(|attr| Attribute {
+                                    name: attr.name().as_str().to_owned(),
+                                    value: attr.value().map(|v| v.as_str().to_owned()),
+                                })
+                                .collect()
+                        }),
+                        children: vec![],
+                        empty: false,
+                        inner: pos..pos,
+                        line,
+                        column,
+                    };
+                    let trim = Self::take_trim(&mut element.attributes, &mut open, &mut nodes);
+                    if is_void_element(&element.name, html5) {
+                        Self::push(&mut open, &mut nodes, Node::Element(element));
+                        trim_next = trim.after;
+                    } else {
+                        open.push(element);
+                        trims.push(trim);
+                    }
+                }
+                Ty::EmptyElementTag(eet) => {
+                    let (line, column) = counter.position(xml, pos_before);
+                    let mut element = Element {
+                        name: eet.name().as_str().to_owned(),
+                        attributes: eet.attributes().map_or(vec![], |attributes| {
+                            attributes
+                                .into_iter()
+                                .map(|attr| Attribute {
+                                    name: attr.name().as_str().to_owned(),
+                                    value: attr.value().map(|v| v.as_str().to_owned()),
+                                })
+                                .collect()
+                        }),
+                        children: vec![],
+                        empty: true,
+                        inner: pos..pos,
+                        line,
+                        column,
+                    };
+                    let trim = Self::take_trim(&mut element.attributes, &mut open, &mut nodes);
+                    Self::push(&mut open, &mut nodes, Node::Element(element));
+                    trim_next = trim.after;
+                }
+                Ty::EndTag(et) => {
+                    if open.is_empty() {
+                        break;
+                    }
+                    if !html5 || !is_void_element(et.name().as_str(), true) {
+                        trim_next =
+                            Self::close(xml, &mut open, &mut trims, &mut nodes, pos_before).after;
+                    }
+                }
+                Ty::ProcessingInstruction(pi) => {
+                    if let Some(instructions) = pi.instructions() {
+                        let (line, column) = counter.position(xml, pos_before);
+                        Self::push(
+                            &mut open,
+                            &mut nodes,
+                            Node::ProcessingInstruction {
+                                target: pi.target().as_str().to_owned(),
+                                instructions: instructions.as_str().to_owned(),
+                                line,
+                                column,
+                            },
+                        );
+                    }
+                }
+                Ty::Characters(_) => {
+                    let mut text = token.as_bytes();
+                    if trim_start_of_text {
+                        text = trim_start(text);
+                    }
+                    let text = if minify && !open.iter().any(|e| is_preserved(&e.name)) {
+                        minify_text(text, &xml[..pos_before], &xml[pos..])
+                    } else {
+                        text.to_vec()
+                    };
+                    if !text.is_empty() {
+                        Self::push(&mut open, &mut nodes, Node::Text(text));
+                    }
+                }
+                Ty::Cdata(_) | Ty::Comment(_) | Ty::Declaration(_) => {
+                    Self::push(&mut open, &mut nodes, Node::Text(token.as_bytes().to_vec()));
+                }
+            }
+            pos_before = pos;
+        }
+        while !open.is_empty() {
+            Self::close(xml, &mut open, &mut trims, &mut nodes, xml.len());
+        }
+        nodes
+    }
+}
+
+/// Whitespace trimming requested by wd:trim="before after inner" (all of them if empty).
+#[derive(Clone, Copy, Default)]
+struct Trim {
+    before: bool,
+    after: bool,
+    inner: bool,
+}
+impl Trim {
+    fn parse(value: &str) -> Self {
+        let words: Vec<_> = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .collect();
+        if words.is_empty() || words.contains(&"all") {
+            return Self {
+                before: true,
+                after: true,
+                inner: true,
+            };
+        }
+        Self {
+            before: words.contains(&"before"),
+            after: words.contains(&"after"),
+            inner: words.contains(&"inner"),
+        }
+    }
+}
+
+fn trim_start(text: &[u8]) -> &[u8] {
+    let start = text
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(text.len());
+    &text[start..]
+}
+
+fn trim_end(text: &[u8]) -> &[u8] {
+    let end = text
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    &text[..end]
+}
+
+fn is_preserved(name: &str) -> bool {
+    matches!(name, "pre" | "textarea" | "script" | "style" | "wd:update")
+}
+
+fn is_block_element(name: &[u8]) -> bool {
+    const BLOCK_ELEMENTS: &[&[u8]] = &[
+        b"address",
+        b"article",
+        b"aside",
+        b"blockquote",
+        b"body",
+        b"dd",
+        b"div",
+        b"dl",
+        b"dt",
+        b"fieldset",
+        b"figcaption",
+        b"figure",
+        b"footer",
+        b"form",
+        b"h1",
+        b"h2",
+        b"h3",
+        b"h4",
+        b"h5",
+        b"h6",
+        b"head",
+        b"header",
+        b"hr",
+        b"html",
+        b"li",
+        b"link",
+        b"main",
+        b"meta",
+        b"nav",
+        b"ol",
+        b"p",
+        b"pre",
+        b"section",
+        b"table",
+        b"tbody",
+        b"td",
+        b"tfoot",
+        b"th",
+        b"thead",
+        b"title",
+        b"tr",
+        b"ul",
+    ];
+    BLOCK_ELEMENTS.contains(&name.to_ascii_lowercase().as_slice())
+}
+
+/// Name of the start or end tag at the beginning of xml.
+fn tag_name(xml: &[u8]) -> Option<&[u8]> {
+    let name = xml.strip_prefix(b"<")?;
+    let name = name.strip_prefix(b"/").unwrap_or(name);
+    let end = name
+        .iter()
+        .position(|c| c.is_ascii_whitespace() || *c == b'/' || *c == b'>')
+        .unwrap_or(name.len());
+    Some(&name[..end])
+}
+
+/// Collapses runs of whitespace into a space. Whitespace-only text spanning lines is dropped
+/// when the tags before and after it are block-level, where it is not rendered.
+fn minify_text(text: &[u8], before: &[u8], after: &[u8]) -> Vec<u8> {
+    if text.iter().all(|c| c.is_ascii_whitespace())
+        && text.contains(&b'\n')
+        && before.ends_with(b">")
+        && before
+            .iter()
+            .rposition(|c| *c == b'<')
+            .and_then(|i| tag_name(&before[i..]))
+            .is_some_and(is_block_element)
+        && tag_name(after).is_some_and(is_block_element)
+    {
+        return vec![];
+    }
+    let mut r = Vec::with_capacity(text.len());
+    let mut space = false;
+    for c in text {
+        if c.is_ascii_whitespace() {
+            space = true;
+        } else {
+            if space {
+                r.push(b' ');
+                space = false;
+            }
+            r.push(*c);
+        }
+    }
+    if space {
+        r.push(b' ');
+    }
+    r
+}
@@ -4,21 +4,20 @@ use std::{
     sync::Arc,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use async_recursion::async_recursion;
 use base64::{engine::general_purpose, Engine};
 use chrono::DateTime;
 use hashbrown::HashMap;
-use maybe_xml::{token::Ty, Reader};
 
 use wild_doc_script::{
-    Activity, CollectionRow, Depends, FieldName, IncludeAdaptor, Pend, SessionRecord, Term, Vars,
-    WildDocValue,
+    Activity, CollectionRow, Depends, FieldName, IncludeAdaptor, Pend, Session, SessionRecord,
+    Term, Vars, WildDocValue,
 };
 
 use crate::{r#const::*, xml_util};
 
-use super::Parser;
+use super::{Node, Output, Parser};
 
 #[derive(Debug, Clone)]
 struct DependError;
@@ -57,100 +56,135 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         )
     }
 
-    pub async fn update(&mut self, xml: &[u8], pos: &mut usize, attr: Vars) -> Result<Vec<u8>> {
-        let mut r = vec![];
-        if let Ok(inner_xml) = self.parse(xml, pos).await {
-            let mut pos = 0;
-            let (updates, on) = self
-                .make_update_struct(inner_xml.as_slice(), &mut pos)
-                .await?;
+    // The database guard is held across the async database API; see `Parser::database`.
+    #[allow(clippy::await_holding_lock)]
+    pub(super) async fn update(
+        &mut self,
+        nodes: &[Node],
+        attr: Vars,
+        r: &mut Output<'_>,
+    ) -> Result<()> {
+        self.update_on.push(None);
+        let inner_xml = self.parse_to_vec(nodes).await;
+        let on = self.update_on.pop().flatten();
+        let template = self.compile(inner_xml?);
+        let updates = self
+            .make_update_struct(&template.source, &template.nodes)
+            .await?;
 
-            let mut commit_rows = vec![];
-            let mut session_rows = vec![];
+        let mut commit_rows = vec![];
+        let mut session_rows = vec![];
 
-            if !self.sessions.last().is_some()
-                || attr
-                    .get(&*WITHOUT_SESSION)
-                    .and_then(|v| v.as_bool())
-                    .map_or(false, |v| *v)
-            {
-                for record in updates.into_iter() {
-                    match record {
-                        SessionRecord::Update {
-                            collection_id,
-                            row,
-                            activity,
-                            term_begin,
-                            term_end,
-                            fields,
-                            depends,
-                            pends,
-                        } => {
-                            commit_rows.extend(
-                                self.record_update(
-                                    collection_id,
-                                    row,
-                                    activity,
-                                    term_begin,
-                                    term_end,
-                                    fields,
-                                    &depends,
-                                    pends,
-                                )
-                                .await,
-                            );
-                        }
-                        SessionRecord::Delete { collection_id, row } => {
-                            if collection_id.get() > 0 {
-                                self.database
-                                    .write()
-                                    .delete(&CollectionRow::new(collection_id, row))
-                                    .await;
-                            }
+        if self.sessions.last().is_none()
+            || attr
+                .get(&*WITHOUT_SESSION)
+                .and_then(|v| v.as_bool())
+                .is_some_and(|v| *v)
+        {
+            for record in updates.into_iter() {
+                match record {
+                    SessionRecord::Update {
+                        collection_id,
+                        row,
+                        activity,
+                        term_begin,
+                        term_end,
+                        fields,
+                        depends,
+                        pends,
+                    } => {
+                        commit_rows.extend(
+                            self.record_update(
+                                collection_id,
+                                row,
+                                activity,
+                                term_begin,
+                                term_end,
+                                fields,
+                                &depends,
+                                pends,
+                            )
+                            .await,
+                        );
+                    }
+                    SessionRecord::Delete { collection_id, row } => {
+                        if collection_id.get() > 0 {
+                            let collection_row = CollectionRow::new(collection_id, row);
+                            self.database.write().delete(&collection_row).await;
+                            self.update_fulltext(&[collection_row]);
                         }
                     }
                 }
-            } else {
-                if let Some(session_state) = self.sessions.last_mut() {
-                    session_rows = self
-                        .database
-                        .write()
-                        .update(&mut session_state.session, updates)
-                        .await;
-                    if let Some(commit) = attr.get(&*COMMIT) {
-                        if commit.as_bool().map_or(false, |v| *v) {
-                            commit_rows = self
-                                .database
-                                .write()
-                                .commit(&mut session_state.session)
-                                .await;
-                        }
+            }
+        } else {
+            if let Some(session_state) = self.sessions.last_mut() {
+                session_rows = self
+                    .database
+                    .write()
+                    .update(&mut session_state.session, updates)
+                    .await;
+            }
+            if let Some(commit) = attr.get(&*COMMIT) {
+                if commit.as_bool().is_some_and(|v| *v) {
+                    if let Some(mut session_state) = self.sessions.pop() {
+                        commit_rows = self.commit(&mut session_state.session).await;
+                        self.sessions.push(session_state);
                     }
                 }
             }
-            if let Some((on_xml, on_vars)) = on {
-                let mut new_vars = Vars::new();
-                new_vars.insert(
-                    if let Some(var) = on_vars.get(&*VAR) {
-                        var.as_string()
-                    } else {
-                        Arc::clone(&UPDATE)
-                    },
-                    WildDocValue::Object(
-                        [
-                            (Arc::clone(&COMMIT_ROWS), self.rows2val(commit_rows)),
-                            (Arc::clone(&SESSION_ROWS), self.rows2val(session_rows)),
-                        ]
-                        .into(),
-                    ),
-                );
-                let mut pos = 0;
-                self.stack.push(new_vars);
-                r = self.parse(on_xml, &mut pos).await?;
-                self.stack.pop();
+        }
+        if let Some((on, on_vars)) = on {
+            let mut new_vars = Vars::new();
+            new_vars.insert(
+                if let Some(var) = on_vars.get(&*VAR) {
+                    var.as_string()
+                } else {
+                    Arc::clone(&UPDATE)
+                },
+                WildDocValue::Object(
+                    [
+                        (Arc::clone(&COMMIT_ROWS), self.rows2val(commit_rows)),
+                        (Arc::clone(&SESSION_ROWS), self.rows2val(session_rows)),
+                    ]
+                    .into(),
+                ),
+            );
+            self.stack.push(new_vars);
+            self.parse(&on.children, r).await?;
+            self.stack.pop();
+        }
+        Ok(())
+    }
+
+    /// Commits the session and reads the rows it wrote into the fulltext index.
+    // The database guard is held across the async database API; see `Parser::database`.
+    #[allow(clippy::await_holding_lock)]
+    pub(super) async fn commit(&self, session: &mut Session) -> Vec<CollectionRow> {
+        let mut rows = vec![];
+        let collection_ids = self.fulltext.lock().collection_ids();
+        for collection_id in collection_ids {
+            if let Some(temporary_collection) = session.temporary_collection(collection_id) {
+                rows.extend(temporary_collection.keys().filter_map(|row| {
+                    NonZeroU32::new(row.get().try_into().ok()?)
+                        .map(|row| CollectionRow::new(collection_id, row))
+                }));
+            }
+        }
+        let commit_rows = self.database.write().commit(session).await;
+        rows.extend(commit_rows.iter().cloned());
+        self.update_fulltext(&rows);
+        commit_rows
+    }
+
+    /// Reads the rows into the fulltext index again after they were written.
+    fn update_fulltext(&self, rows: &[CollectionRow]) {
+        let database = self.database.read();
+        let mut fulltext = self.fulltext.lock();
+        for row in rows {
+            if let Some(collection) = database.collection(row.collection_id()) {
+                fulltext.update(collection, row.collection_id(), row.row());
             }
         }
-        Ok(r)
     }
 
     #[async_recursion(?Send)]
@@ -198,6 +232,8 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
         rows
     }
 
+    // The database guard is held across the async database API; see `Parser::database`.
+    #[allow(clippy::too_many_arguments, clippy::await_holding_lock)]
     async fn record_update(
         &self,
         collection_id: NonZeroI32,
@@ -214,11 +250,9 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
             if collection_id.get() > 0 {
                 let collection_row =
                     if let Some(collection) = self.database.write().collection_mut(collection_id) {
-                        Arc::new(
-                            collection
-                                .update(row, activity, term_begin, term_end, fields)
-                                .await,
-                        );
+                        collection
+                            .update(row, activity, term_begin, term_end, fields)
+                            .await;
                         Some(CollectionRow::new(collection_id, row))
                     } else {
                         None
@@ -230,13 +264,14 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                             .relation_mut()
                             .delete_pends_by_collection_row(collection_row)
                             .await;
-                        for d in depends.into_iter() {
+                        for d in depends.iter() {
                             self.database
                                 .write()
                                 .register_relation(&d.0, &d.1, collection_row)
                                 .await;
                         }
                     }
+                    self.update_fulltext(std::slice::from_ref(collection_row));
                     rows.push(collection_row.clone());
                     self.update_pends(collection_row, pends).await;
                 }
@@ -256,15 +291,16 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
                     };
                 if let Some(ref collection_row) = collection_row {
                     if let Depends::Overwrite(depends) = depends {
-                        for (depend_key, depend_row) in depends.into_iter() {
+                        for (depend_key, depend_row) in depends.iter() {
                             self.database
                                 .write()
-                                .register_relation(&depend_key, depend_row, collection_row)
+                                .register_relation(depend_key, depend_row, collection_row)
                                 .await;
                         }
                     }
+                    self.update_fulltext(std::slice::from_ref(collection_row));
                     rows.push(collection_row.clone());
-                    self.update_pends(&collection_row, pends).await;
+                    self.update_pends(collection_row, pends).await;
                 }
             }
         }
@@ -315,239 +351,204 @@ impl<I: IncludeAdaptor + Send> Parser<I> {
     }
 
     #[async_recursion(?Send)]
-    async fn make_update_struct<'a, 'b>(
+    async fn make_update_struct(
         &mut self,
-        xml: &'a [u8],
-        pos: &mut usize,
-    ) -> Result<(Vec<SessionRecord>, Option<(&'b [u8], Vars)>)>
-    where
-        'a: 'b,
-    {
+        source: &[u8],
+        nodes: &[Node],
+    ) -> Result<Vec<SessionRecord>> {
         let mut updates = Vec::new();
-        let mut on = None;
-
-        let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
-        while let Some(token) = reader.tokenize(pos) {
-            match token.ty() {
-                Ty::StartTag(st) => {
-                    match st.name().as_bytes() {
-                        b"wd:on" => {
-                            let begin = *pos;
-                            let (inner, _) = xml_util::to_end(xml, pos);
-                            on = Some((
-                                &xml[begin..inner],
-                                self.vars_from_attibutes(st.attributes()).await,
-                            ));
-                        }
-                        b"collection" => {
-                            let attr = self.vars_from_attibutes(st.attributes()).await;
-                            if let Some(collection_name) = attr.get(&*NAME) {
-                                let collection_id = self
-                                    .database
-                                    .write()
-                                    .collection_id_or_create(&collection_name.as_string());
-
-                                let mut pends = Vec::new();
-                                let mut depends = Vec::new();
-                                let mut fields = HashMap::new();
-                                let mut deps = 1;
-                                while let Some(token) = reader.tokenize(pos) {
-                                    match token.ty() {
-                                        Ty::StartTag(st) => {
-                                            deps += 1;
 
-                                            let attr =
-                                                self.vars_from_attibutes(st.attributes()).await;
-                                            match st.name().as_bytes() {
-                                                b"field" => {
-                                                    let begin = *pos;
-                                                    let (inner, _) = xml_util::to_end(xml, pos);
+        for element in nodes.iter().filter_map(Node::element) {
+            match element.name.as_str() {
+                "collection" => {
+                    let attr = self
+                        .vars_from_attibutes(&element.attributes)
+                        .await
+                        .and_then(|attr| {
+                            self.required(&attr, &[&*NAME])?;
+                            Ok(attr)
+                        })
+                        .map_err(|e| self.located_error(element, e))?;
+                    if let Some(collection_name) = attr.get(&*NAME) {
+                        let collection_id = self
+                            .database
+                            .write()
+                            .collection_id_or_create(&collection_name.as_string());
 
-                                                    if let Some(field_name) = attr.get(&*NAME) {
-                                                        let mut value = std::str::from_utf8(
-                                                            &xml[begin..inner],
-                                                        )?
-                                                        .replace("&gt;", ">")
-                                                        .replace("&lt;", "<")
-                                                        .replace("&#039;", "'")
-                                                        .replace("&quot;", "\"")
-                                                        .replace("&amp;", "&")
-                                                        .into_bytes();
+                        let mut pends = Vec::new();
+                        let mut depends = Vec::new();
+                        let mut fields = HashMap::new();
+                        self.collection_inner(
+                            source,
+                            &element.children,
+                            &mut fields,
+                            &mut depends,
+                            &mut pends,
+                        )
+                        .await?;
 
-                                                        if let Some(base64_decode) =
-                                                            attr.get(&*BASE64)
-                                                        {
-                                                            if base64_decode
-                                                                .as_bool()
-                                                                .map_or(false, |v| *v)
-                                                            {
-                                                                value =
-                                                                general_purpose::STANDARD_NO_PAD
-                                                                    .decode(value)
-                                                                    .unwrap();
-                                                            }
-                                                        }
-                                                        fields.insert(
-                                                            FieldName::new(field_name.to_string()),
-                                                            value,
-                                                        );
-                                                    }
-                                                }
-                                                b"pends" => {
-                                                    //TODO: proc for _on_xml?
-                                                    let (pends_tmp, _on_xml) =
-                                                        self.make_update_struct(xml, pos).await?;
+                        let row: i64 = attr
+                            .get(&*ROW)
+                            .and_then(|v| v.as_string().parse::<i64>().ok())
+                            .unwrap_or(0);
 
-                                                    if let Some(key) = attr.get(&*KEY) {
-                                                        pends.push(Pend {
-                                                            key: key.as_string(),
-                                                            records: pends_tmp,
-                                                        });
-                                                    }
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                        Ty::EmptyElementTag(eet) => {
-                                            let name = eet.name();
-                                            match name.as_bytes() {
-                                                b"depend" => {
-                                                    let attr = self
-                                                        .vars_from_attibutes(eet.attributes())
-                                                        .await;
-                                                    self.depend(&attr, &mut depends)?;
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                        Ty::EndTag(et) => {
-                                            if et.name().as_bytes() == b"collection" {
-                                                break;
-                                            }
-                                            deps -= 1;
-                                            if deps < 0 {
-                                                return Err(anyhow!("invalid XML"));
-                                            }
-                                        }
-                                        Ty::Characters(_)
-                                        | Ty::Cdata(_)
-                                        | Ty::Comment(_)
-                                        | Ty::Declaration(_)
-                                        | Ty::ProcessingInstruction(_) => {}
-                                    }
+                        let (collection_id, row) = if row < 0 {
+                            (-collection_id, (-row) as u32)
+                        } else {
+                            (collection_id, row as u32)
+                        };
+                        if attr
+                            .get(&*DELETE)
+                            .and_then(|v| v.as_bool())
+                            .is_some_and(|v| *v)
+                        {
+                            if row != 0 {
+                                updates.push(SessionRecord::Delete {
+                                    collection_id,
+                                    row: unsafe { NonZeroU32::new_unchecked(row) },
+                                });
+                            }
+                        } else {
+                            let mut activity = Activity::Active;
+                            if let Some(str) = attr.get(&*ACTIVITY) {
+                                let str = str.as_string();
+                                let str = str.as_str();
+                                if str == "inactive" || str == "0" {
+                                    activity = Activity::Inactive;
                                 }
-
-                                let row: i64 = attr
-                                    .get(&*ROW)
-                                    .and_then(|v| v.as_string().parse::<i64>().ok())
-                                    .unwrap_or(0);
-
-                                let (collection_id, row) = if row < 0 {
-                                    (-collection_id, (-row) as u32)
-                                } else {
-                                    (collection_id, row as u32)
-                                };
-                                if attr
-                                    .get(&*DELETE)
-                                    .and_then(|v| v.as_bool())
-                                    .map_or(false, |v| *v)
-                                {
-                                    if row != 0 {
-                                        updates.push(SessionRecord::Delete {
-                                            collection_id,
-                                            row: unsafe { NonZeroU32::new_unchecked(row) },
-                                        });
-                                    }
-                                } else {
-                                    let mut activity = Activity::Active;
-                                    if let Some(str) = attr.get(&*ACTIVITY) {
-                                        let str = str.as_string();
-                                        let str = str.as_str();
-                                        if str == "inactive" || str == "0" {
-                                            activity = Activity::Inactive;
-                                        }
-                                    }
-                                    let mut term_begin = Term::Default;
-                                    if let Some(str) = attr.get(&*TERM_BEGIN) {
-                                        let str = str.as_string();
-                                        let str = str.as_str();
-                                        if str != "" {
-                                            if let Ok(t) =
-                                                DateTime::parse_from_str(&str, "%Y-%m-%d %H:%M:%S")
-                                                    .map(|v| v.timestamp())
-                                            {
-                                                term_begin = Term::Overwrite(t as u64)
-                                            }
-                                        }
+                            }
+                            let mut term_begin = Term::Default;
+                            if let Some(str) = attr.get(&*TERM_BEGIN) {
+                                let str = str.as_string();
+                                let str = str.as_str();
+                                if !str.is_empty() {
+                                    if let Ok(t) =
+                                        DateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S")
+                                            .map(|v| v.timestamp())
+                                    {
+                                        term_begin = Term::Overwrite(t as u64)
                                     }
-                                    let mut term_end = Term::Default;
-                                    if let Some(str) = attr.get(&*TERM_END) {
-                                        let str = str.as_string();
-                                        let str = str.as_str();
-                                        if str != "" {
-                                            if let Ok(t) =
-                                                DateTime::parse_from_str(&str, "%Y-%m-%d %H:%M:%S")
-                                                    .map(|v| v.timestamp())
-                                            {
-                                                term_end = Term::Overwrite(t as u64)
-                                            }
-                                        }
+                                }
+                            }
+                            let mut term_end = Term::Default;
+                            if let Some(str) = attr.get(&*TERM_END) {
+                                let str = str.as_string();
+                                let str = str.as_str();
+                                if !str.is_empty() {
+                                    if let Ok(t) =
+                                        DateTime::parse_from_str(str, "%Y-%m-%d %H:%M:%S")
+                                            .map(|v| v.timestamp())
+                                    {
+                                        term_end = Term::Overwrite(t as u64)
                                     }
-                                    updates.push(if row == 0 {
-                                        SessionRecord::Update {
-                                            collection_id,
-                                            row: None,
-                                            activity,
-                                            term_begin,
-                                            term_end,
-                                            fields,
-                                            depends: Depends::Overwrite(depends),
-                                            pends,
-                                        }
-                                    } else {
-                                        let inherit_depend_if_empty = if let Some(str) =
-                                            attr.get(&*INHERIT_DEPEND_IF_EMPTY)
-                                        {
-                                            str.as_bool().map_or(false, |v| *v)
-                                        } else {
-                                            false
-                                        };
-                                        SessionRecord::Update {
-                                            collection_id,
-                                            row: NonZeroU32::new(row),
-                                            activity,
-                                            term_begin,
-                                            term_end,
-                                            fields,
-                                            depends: if inherit_depend_if_empty
-                                                && depends.len() == 0
-                                            {
-                                                Depends::Default
-                                            } else {
-                                                Depends::Overwrite(depends)
-                                            },
-                                            pends,
-                                        }
-                                    });
                                 }
                             }
+                            updates.push(if row == 0 {
+                                SessionRecord::Update {
+                                    collection_id,
+                                    row: None,
+                                    activity,
+                                    term_begin,
+                                    term_end,
+                                    fields,
+                                    depends: Depends::Overwrite(depends),
+                                    pends,
+                                }
+                            } else {
+                                let inherit_depend_if_empty =
+                                    if let Some(str) = attr.get(&*INHERIT_DEPEND_IF_EMPTY) {
+                                        str.as_bool().is_some_and(|v| *v)
+                                    } else {
+                                        false
+                                    };
+                                SessionRecord::Update {
+                                    collection_id,
+                                    row: NonZeroU32::new(row),
+                                    activity,
+                                    term_begin,
+                                    term_end,
+                                    fields,
+                                    depends: if inherit_depend_if_empty && depends.is_empty() {
+                                        Depends::Default
+                                    } else {
+                                        Depends::Overwrite(depends)
+                                    },
+                                    pends,
+                                }
+                            });
                         }
+                    }
+                }
+                _ => {
+                    if self.strict {
+                        return Err(self.unknown_tag(element));
+                    }
+                    updates.extend(self.make_update_struct(source, &element.children).await?);
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    #[async_recursion(?Send)]
+    async fn collection_inner(
+        &mut self,
+        source: &[u8],
+        nodes: &[Node],
+        fields: &mut HashMap<FieldName, Vec<u8>>,
+        depends: &mut Vec<(Arc<String>, CollectionRow)>,
+        pends: &mut Vec<Pend>,
+    ) -> Result<()> {
+        for element in nodes.iter().filter_map(Node::element) {
+            let attr = self
+                .vars_from_attibutes(&element.attributes)
+                .await
+                .and_then(|attr| {
+                    match (element.name.as_str(), element.empty) {
+                        ("field", false) => self.required(&attr, &[&*NAME])?,
+                        ("pends", false) => self.required(&attr, &[&*KEY])?,
+                        ("depend", true) => self.required(&attr, &[&*KEY, &*COLLECTION, &*ROW])?,
                         _ => {}
                     }
+                    Ok(attr)
+                })
+                .map_err(|e| self.located_error(element, e))?;
+            match (element.name.as_str(), element.empty) {
+                ("field", false) => {
+                    if let Some(field_name) = attr.get(&*NAME) {
+                        let mut value = xml_util::unescape(std::str::from_utf8(
+                            &source[element.inner.clone()],
+                        )?)
+                        .into_bytes();
+                        if let Some(base64_decode) = attr.get(&*BASE64) {
+                            if base64_decode.as_bool().is_some_and(|v| *v) {
+                                value = general_purpose::STANDARD_NO_PAD.decode(value).unwrap();
+                            }
+                        }
+                        fields.insert(FieldName::new(field_name.to_string()), value);
+                    }
                 }
-                Ty::EndTag(_) => {
-                    break;
+                ("pends", false) => {
+                    let pends_tmp = self.make_update_struct(source, &element.children).await?;
+                    if let Some(key) = attr.get(&*KEY) {
+                        pends.push(Pend {
+                            key: key.as_string(),
+                            records: pends_tmp,
+                        });
+                    }
+                }
+                ("depend", true) => {
+                    self.depend(&attr, depends)?;
                 }
-                Ty::Characters(_)
-                | Ty::Cdata(_)
-                | Ty::Comment(_)
-                | Ty::Declaration(_)
-                | Ty::ProcessingInstruction(_) => {}
                 _ => {
-                    break;
+                    if self.strict {
+                        return Err(self.unknown_tag(element));
+                    }
+                    self.collection_inner(source, &element.children, fields, depends, pends)
+                        .await?;
                 }
             }
         }
-        Ok((updates, on))
+        Ok(())
     }
 }
@@ -0,0 +1,371 @@
+use std::{borrow::Cow, cmp::Ordering, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Result};
+
+use parking_lot::Mutex;
+use wild_doc_script::{async_trait, IncludeAdaptor, Stack, WildDocScript, WildDocValue};
+
+use crate::xml_util;
+
+use super::var::descend;
+
+/// Built-in expression language usable as `name:expr="..."` without a JS runtime.
+///
+/// Supports literals (numbers, "strings", 'strings', true, false, null), variables from the stack,
+/// `.name` / `[expr]` indexing as in the var script, `!` `-` `*` `/` `%` `+` `-` `<` `<=` `>` `>=` `==` `!=` `&&` `||`
+/// and parentheses. `+` concatenates when either side is a string.
+/// `&&` and `||` do not evaluate the right side once the left side decides the result, so `x != null && x > 1` works for null.
+/// `&amp;`, `&lt;`, `&gt;`, `&quot;` and `&#039;` are read as the characters they escape so that the attribute can stay well-formed XML.
+pub struct Expr {}
+
+#[async_trait(?Send)]
+impl<I: IncludeAdaptor + Send> WildDocScript<I> for Expr {
+    fn new(_: Arc<Mutex<I>>, _: PathBuf, _: &Stack) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {})
+    }
+
+    async fn evaluate_module(&mut self, _: &str, _: &str, _: &Stack) -> Result<()> {
+        Ok(())
+    }
+
+    async fn eval(&mut self, code: &str, stack: &Stack) -> Result<WildDocValue> {
+        let code = xml_util::unescape(code);
+        let code = code.as_str();
+        let mut parser = ExprParser {
+            tokens: tokenize(code)?,
+            pos: 0,
+            stack,
+            evaluate: true,
+        };
+        let value = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(anyhow!("unexpected {:?} in {}", token, code));
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(serde_json::Number),
+    String(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "[", "]",
+    ".",
+];
+
+fn tokenize(code: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = code.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            // After "." a number is an array index, so "rows.0.1" does not read 0.1.
+            let decimal = tokens.last() != Some(&Token::Op("."));
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || (decimal
+                        && chars[i] == '.'
+                        && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())))
+            {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(
+                serde_json::from_str(&number).map_err(|_| anyhow!("invalid number {}", number))?,
+            ));
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some('\\') => {
+                        if let Some(c) = chars.get(i + 1) {
+                            s.push(*c);
+                        }
+                        i += 2;
+                    }
+                    Some(end) if *end == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => {
+                        s.push(*c);
+                        i += 1;
+                    }
+                    None => return Err(anyhow!("unterminated string in {}", code)),
+                }
+            }
+            tokens.push(Token::String(s));
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if let Some(op) = OPS.iter().find(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(j, c)| chars.get(i + j) == Some(&c))
+        }) {
+            i += op.len();
+            tokens.push(Token::Op(op));
+        } else {
+            return Err(anyhow!("unexpected {} in {}", c, code));
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    stack: &'a Stack,
+    // false while parsing the right side of && or || that the left side has decided.
+    evaluate: bool,
+}
+impl<'a> ExprParser<'a> {
+    fn eat(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        if let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            if ops.contains(op) {
+                self.pos += 1;
+                return Some(*op);
+            }
+        }
+        None
+    }
+
+    fn expect(&mut self, op: &'static str) -> Result<()> {
+        self.eat(&[op])
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("expected {}", op))
+    }
+
+    /// Parses with f, without evaluating when skip.
+    fn skip_if(
+        &mut self,
+        skip: bool,
+        f: fn(&mut Self) -> Result<WildDocValue>,
+    ) -> Result<WildDocValue> {
+        let evaluate = self.evaluate;
+        self.evaluate = evaluate && !skip;
+        let value = f(self);
+        self.evaluate = evaluate;
+        value
+    }
+
+    fn or(&mut self) -> Result<WildDocValue> {
+        let mut left = self.and()?;
+        while self.eat(&["||"]).is_some() {
+            let right = self.skip_if(is_truthy(&left), Self::and)?;
+            left = WildDocValue::Bool(is_truthy(&left) || is_truthy(&right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<WildDocValue> {
+        let mut left = self.equality()?;
+        while self.eat(&["&&"]).is_some() {
+            let right = self.skip_if(!is_truthy(&left), Self::equality)?;
+            left = WildDocValue::Bool(is_truthy(&left) && is_truthy(&right));
+        }
+        Ok(left)
+    }
+
+    fn equality(&mut self) -> Result<WildDocValue> {
+        let mut left = self.comparison()?;
+        while let Some(op) = self.eat(&["==", "!="]) {
+            let right = self.comparison()?;
+            let eq = equals(&left, &right);
+            left = WildDocValue::Bool(if op == "==" { eq } else { !eq });
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<WildDocValue> {
+        let mut left = self.additive()?;
+        while let Some(op) = self.eat(&["<", "<=", ">", ">="]) {
+            let right = self.additive()?;
+            if !self.evaluate {
+                continue;
+            }
+            let ordering = compare(&left, &right)
+                .ok_or_else(|| anyhow!("can not compare {} and {}", left, right))?;
+            left = WildDocValue::Bool(match op {
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            });
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<WildDocValue> {
+        let mut left = self.multiplicative()?;
+        while let Some(op) = self.eat(&["+", "-"]) {
+            let right = self.multiplicative()?;
+            if !self.evaluate {
+                continue;
+            }
+            left = match (op, &left, &right) {
+                ("+", WildDocValue::String(_), _) | ("+", _, WildDocValue::String(_)) => {
+                    WildDocValue::String(Arc::new(
+                        left.as_string().to_string() + right.as_string().as_str(),
+                    ))
+                }
+                _ => arithmetic(op, &left, &right)?,
+            };
+        }
+        Ok(left)
+    }
+
+    fn multiplicative(&mut self) -> Result<WildDocValue> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.eat(&["*", "/", "%"]) {
+            let right = self.unary()?;
+            if self.evaluate {
+                left = arithmetic(op, &left, &right)?;
+            }
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<WildDocValue> {
+        match self.eat(&["!", "-"]) {
+            Some("!") => Ok(WildDocValue::Bool(!is_truthy(&self.unary()?))),
+            Some(_) => {
+                let value = self.unary()?;
+                if self.evaluate {
+                    arithmetic("-", &WildDocValue::Number(0.into()), &value)
+                } else {
+                    Ok(value)
+                }
+            }
+            None => Ok(self.postfix()?.into_owned()),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Cow<'a, WildDocValue>> {
+        let mut value = self.primary()?;
+        loop {
+            let key = if self.eat(&["."]).is_some() {
+                match self.tokens.get(self.pos).cloned() {
+                    Some(Token::Ident(name)) => {
+                        self.pos += 1;
+                        WildDocValue::String(Arc::new(name))
+                    }
+                    Some(Token::Number(n)) => {
+                        self.pos += 1;
+                        WildDocValue::Number(n)
+                    }
+                    _ => return Err(anyhow!("expected a name after .")),
+                }
+            } else if self.eat(&["["]).is_some() {
+                let key = self.or()?;
+                self.expect("]")?;
+                key
+            } else {
+                return Ok(value);
+            };
+            value = descend(value, &key).unwrap_or(Cow::Owned(WildDocValue::Null));
+        }
+    }
+
+    fn primary(&mut self) -> Result<Cow<'a, WildDocValue>> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("unexpected end of expression"))?;
+        self.pos += 1;
+        let stack = self.stack;
+        Ok(match token {
+            Token::Number(n) => Cow::Owned(WildDocValue::Number(n)),
+            Token::String(s) => Cow::Owned(WildDocValue::String(Arc::new(s))),
+            Token::Ident(name) => match name.as_str() {
+                "true" => Cow::Owned(WildDocValue::Bool(true)),
+                "false" => Cow::Owned(WildDocValue::Bool(false)),
+                "null" => Cow::Owned(WildDocValue::Null),
+                _ => stack
+                    .get(&Arc::new(name))
+                    .map_or(Cow::Owned(WildDocValue::Null), Cow::Borrowed),
+            },
+            Token::Op("(") => {
+                let value = self.or()?;
+                self.expect(")")?;
+                Cow::Owned(value)
+            }
+            Token::Op(op) => return Err(anyhow!("unexpected {}", op)),
+        })
+    }
+}
+
+fn is_truthy(value: &WildDocValue) -> bool {
+    match value {
+        WildDocValue::Null => false,
+        WildDocValue::Bool(v) => *v,
+        WildDocValue::Number(v) => v.as_f64().is_some_and(|v| v != 0.0),
+        WildDocValue::String(v) => !v.is_empty(),
+        WildDocValue::Array(v) => !v.is_empty(),
+        WildDocValue::Object(v) => !v.is_empty(),
+        WildDocValue::Binary(v) => !v.is_empty(),
+        _ => true,
+    }
+}
+
+fn equals(left: &WildDocValue, right: &WildDocValue) -> bool {
+    match (left, right) {
+        (WildDocValue::Number(l), WildDocValue::Number(r)) => l.as_f64() == r.as_f64(),
+        _ => left == right,
+    }
+}
+
+fn compare(left: &WildDocValue, right: &WildDocValue) -> Option<Ordering> {
+    match (left, right) {
+        (WildDocValue::String(l), WildDocValue::String(r)) => Some(l.cmp(r)),
+        _ => left.as_f64()?.partial_cmp(&right.as_f64()?),
+    }
+}
+
+fn arithmetic(op: &str, left: &WildDocValue, right: &WildDocValue) -> Result<WildDocValue> {
+    if let (WildDocValue::Number(l), WildDocValue::Number(r)) = (left, right) {
+        if let (Some(l), Some(r)) = (l.as_i64(), r.as_i64()) {
+            let v = match op {
+                "+" => l.checked_add(r),
+                "-" => l.checked_sub(r),
+                "*" => l.checked_mul(r),
+                "%" => l.checked_rem(r),
+                _ => None,
+            };
+            if let Some(v) = v {
+                return Ok(WildDocValue::Number(v.into()));
+            }
+        }
+    }
+    let (l, r) = left
+        .as_f64()
+        .zip(right.as_f64())
+        .ok_or_else(|| anyhow!("{} requires numbers: {} {} {}", op, left, op, right))?;
+    let v = match op {
+        "+" => l + r,
+        "-" => l - r,
+        "*" => l * r,
+        "/" => l / r,
+        _ => l % r,
+    };
+    Ok(WildDocValue::from_f64(v))
+}
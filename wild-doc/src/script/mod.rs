@@ -1,2 +1,5 @@
+mod expr;
 mod var;
-pub use var::Var;
\ No newline at end of file
+
+pub use expr::Expr;
+pub use var::Var;
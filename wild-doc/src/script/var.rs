@@ -1,11 +1,21 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{borrow::Cow, path::PathBuf, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use parking_lot::Mutex;
 use wild_doc_script::{async_trait, IncludeAdaptor, Stack, WildDocScript, WildDocValue};
 
-pub struct Var {}
+/// Resolves paths such as `row.field.name`, `rows[i]`, `map["a.b"]`, `list[-1]`, `user?.name` and `list.len`.
+/// In strict mode a missing path is an error unless it is reached through `?.`.
+pub struct Var {
+    strict: bool,
+}
+impl Var {
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
 
 #[async_trait(?Send)]
 impl<I: IncludeAdaptor + Send> WildDocScript<I> for Var {
@@ -13,7 +23,7 @@ impl<I: IncludeAdaptor + Send> WildDocScript<I> for Var {
     where
         Self: Sized,
     {
-        Ok(Self {})
+        Ok(Self { strict: false })
     }
 
     async fn evaluate_module(&mut self, _: &str, _: &str, _: &Stack) -> Result<()> {
@@ -21,78 +31,177 @@ impl<I: IncludeAdaptor + Send> WildDocScript<I> for Var {
     }
 
     async fn eval(&mut self, code: &str, stack: &Stack) -> Result<WildDocValue> {
-        let mut splited = code.split(".");
-        if let Some(root) = splited.next() {
-            if let Some(mut next_value) = stack.get(&Arc::new(root.into())) {
-                loop {
-                    if let Some(next) = splited.next() {
-                        match next_value {
-                            WildDocValue::Object(map) => {
-                                if let Some(v) = map.get(&Arc::new(next.into())) {
-                                    next_value = v;
-                                } else {
-                                    break;
-                                }
-                            }
-                            WildDocValue::Array(map) => {
-                                let mut found = false;
-                                if let Ok(v) = next.parse::<usize>() {
-                                    if let Some(v) = map.get(v) {
-                                        found = true;
-                                        next_value = v;
-                                    }
-                                }
-                                if !found {
-                                    break;
-                                }
-                            }
-                            WildDocValue::SearchResult(result) => {
-                                if next == "rows" {
-                                    if let Some(next) = splited.next() {
-                                        if next == "len" {
-                                            return Ok(WildDocValue::Number(
-                                                result.rows().len().into(),
-                                            ));
-                                        }
-                                    } else {
-                                        return Ok(WildDocValue::Array(
-                                            result
-                                                .rows()
-                                                .into_iter()
-                                                .map(|v| WildDocValue::Number(v.get().into()))
-                                                .collect(),
-                                        ));
-                                    }
-                                }
-                            }
-                            WildDocValue::SessionSearchResult(result) => {
-                                if next == "rows" {
-                                    if let Some(next) = splited.next() {
-                                        if next == "len" {
-                                            return Ok(WildDocValue::Number(
-                                                result.rows().len().into(),
-                                            ));
-                                        }
-                                    } else {
-                                        return Ok(WildDocValue::Array(
-                                            result
-                                                .rows()
-                                                .into_iter()
-                                                .map(|v| WildDocValue::Number(v.get().into()))
-                                                .collect(),
-                                        ));
-                                    }
-                                }
-                            }
-                            _ => break,
-                        }
-                    } else {
-                        return Ok(next_value.clone());
+        resolve(code.trim(), stack, self.strict)
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(i64),
+    Var(String),
+}
+
+fn resolve(code: &str, stack: &Stack, strict: bool) -> Result<WildDocValue> {
+    let (root, segments) = parse_path(code)?;
+    // `?.` allows the value before it to be missing as well as the value it reaches.
+    let followed_by_optional = |i: usize| segments.get(i).is_some_and(|(_, optional)| *optional);
+    let mut value = if let Some(value) = stack.get(&Arc::new(root)) {
+        Cow::Borrowed(value)
+    } else if strict && !followed_by_optional(0) {
+        return Err(anyhow!("{} is not defined", code));
+    } else {
+        return Ok(WildDocValue::Null);
+    };
+    for (i, (segment, optional)) in segments.iter().enumerate() {
+        let key = match segment {
+            Segment::Key(key) => WildDocValue::String(Arc::new(key.to_owned())),
+            Segment::Index(index) => WildDocValue::Number((*index).into()),
+            Segment::Var(path) => resolve(path, stack, strict)?,
+        };
+        if let Some(child) = descend(value, &key) {
+            value = child;
+        } else if strict && !optional && !followed_by_optional(i + 1) {
+            return Err(anyhow!("{} is not found in {}", key, code));
+        } else {
+            return Ok(WildDocValue::Null);
+        }
+    }
+    Ok(value.into_owned())
+}
+
+fn parse_path(code: &str) -> Result<(String, Vec<(Segment, bool)>)> {
+    let chars: Vec<char> = code.chars().collect();
+    let name = |i: &mut usize| {
+        let start = *i;
+        while *i < chars.len() && !matches!(chars[*i], '.' | '[' | '?') {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+
+    let mut i = 0;
+    let root = name(&mut i);
+    let mut segments = vec![];
+    while i < chars.len() {
+        let optional = chars[i] == '?';
+        if optional {
+            if chars.get(i + 1) != Some(&'.') {
+                return Err(anyhow!("expected ?. in {}", code));
+            }
+            i += 2;
+        } else if chars[i] == '.' {
+            i += 1;
+        }
+        if chars.get(i) == Some(&'[') {
+            i += 1;
+            let segment = if let Some(quote) = chars.get(i).filter(|c| **c == '"' || **c == '\'') {
+                let start = i + 1;
+                let end = chars[start..]
+                    .iter()
+                    .position(|c| c == quote)
+                    .map(|p| start + p)
+                    .ok_or_else(|| anyhow!("unterminated string in {}", code))?;
+                i = end + 1;
+                Segment::Key(chars[start..end].iter().collect())
+            } else {
+                let start = i;
+                let mut depth = 0;
+                while i < chars.len() && (chars[i] != ']' || depth > 0) {
+                    match chars[i] {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
                     }
+                    i += 1;
                 }
+                let inner = chars[start..i].iter().collect::<String>();
+                let inner = inner.trim();
+                if let Ok(index) = inner.parse::<i64>() {
+                    Segment::Index(index)
+                } else {
+                    Segment::Var(inner.to_owned())
+                }
+            };
+            if chars.get(i) != Some(&']') {
+                return Err(anyhow!("expected ] in {}", code));
             }
+            i += 1;
+            segments.push((segment, optional));
+        } else {
+            segments.push((Segment::Key(name(&mut i)), optional));
         }
+    }
+    Ok((root, segments))
+}
 
-        Ok(WildDocValue::Null)
+pub(crate) fn descend<'a>(
+    value: Cow<'a, WildDocValue>,
+    key: &WildDocValue,
+) -> Option<Cow<'a, WildDocValue>> {
+    match value {
+        Cow::Borrowed(value) => child(value, key),
+        Cow::Owned(value) => child(&value, key).map(|v| Cow::Owned(v.into_owned())),
+    }
+}
+
+/// Child of value by object key or array index. Negative indexes count from the end.
+/// `rows` of a search result is the array of its rows, and `len` is the length of arrays, objects, strings and search results.
+pub(crate) fn child<'a>(
+    value: &'a WildDocValue,
+    key: &WildDocValue,
+) -> Option<Cow<'a, WildDocValue>> {
+    let len = |len: usize| {
+        (key.as_string().as_str() == "len").then(|| Cow::Owned(WildDocValue::Number(len.into())))
+    };
+    match value {
+        WildDocValue::Object(map) => map
+            .get(&key.as_string())
+            .map(Cow::Borrowed)
+            .or_else(|| len(map.len())),
+        WildDocValue::Array(array) => match key {
+            WildDocValue::Number(index) => index.as_i64(),
+            WildDocValue::String(index) => index.parse().ok(),
+            _ => None,
+        }
+        .and_then(|index| {
+            let index = if index < 0 {
+                array.len() as i64 + index
+            } else {
+                index
+            };
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| array.get(index))
+                .map(Cow::Borrowed)
+        })
+        .or_else(|| len(array.len())),
+        WildDocValue::String(s) => len(s.chars().count()),
+        WildDocValue::SearchResult(result) => {
+            if key.as_string().as_str() == "rows" {
+                Some(Cow::Owned(WildDocValue::Array(
+                    result
+                        .rows()
+                        .iter()
+                        .map(|v| WildDocValue::Number(v.get().into()))
+                        .collect(),
+                )))
+            } else {
+                len(result.rows().len())
+            }
+        }
+        WildDocValue::SessionSearchResult(result) => {
+            if key.as_string().as_str() == "rows" {
+                Some(Cow::Owned(WildDocValue::Array(
+                    result
+                        .rows()
+                        .iter()
+                        .map(|v| WildDocValue::Number(v.get().into()))
+                        .collect(),
+                )))
+            } else {
+                len(result.rows().len())
+            }
+        }
+        _ => None,
     }
 }
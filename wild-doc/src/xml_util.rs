@@ -1,38 +1,78 @@
-use maybe_xml::{token::Ty, Reader};
-
-pub(crate) fn to_end(xml: &[u8], pos: &mut usize) -> (usize, usize) {
-    let mut pos_before = *pos;
-    let mut deps = 0;
-    let reader = Reader::from_str(unsafe { std::str::from_utf8_unchecked(xml) });
-    while let Some(token) = reader.tokenize(pos) {
-        match token.ty() {
-            Ty::StartTag(_) => {
-                deps += 1;
-            }
-            Ty::EndTag(_) => {
-                deps -= 1;
-                if deps < 0 {
-                    return (pos_before, *pos);
-                }
-            }
-            Ty::ProcessingInstruction(_)
-            | Ty::Characters(_)
-            | Ty::Cdata(_)
-            | Ty::Comment(_)
-            | Ty::Declaration(_)
-            | Ty::EmptyElementTag(_) => {}
-        }
-        pos_before = *pos;
-    }
-    (0, 0)
-}
-
 pub(crate) fn quot_unescape(value: &str) -> String {
     value.replace("&#039;", "'").replace("&quot;", "\"")
 }
 
+/// Decodes the five entities that escape_html writes. &amp; is decoded last so "&amp;lt;" stays "&lt;".
+pub(crate) fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&amp;", "&")
+}
+
 pub(crate) fn escape_html(s: &str) -> String {
     s.replace("&", "&amp;")
         .replace("<", "&lt;")
         .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&#039;")
+}
+
+/// The URL unless it has a scheme other than http, https, mailto or tel, such as javascript:, which becomes about:invalid.
+/// Whitespace and control characters are ignored in the scheme as browsers do.
+pub(crate) fn allow_url(s: &str) -> &str {
+    if let Some(end) = s.find([':', '/', '?', '#']) {
+        if s[end..].starts_with(':') {
+            let scheme: String = s[..end]
+                .chars()
+                .filter(|c| !c.is_whitespace() && !c.is_control())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            if !matches!(scheme.as_str(), "http" | "https" | "mailto" | "tel") {
+                return "about:invalid";
+            }
+        }
+    }
+    s
+}
+
+pub(crate) fn escape_url(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            r.push(b as char);
+        } else {
+            r.push_str(&format!("%{:02X}", b));
+        }
+    }
+    r
+}
+
+pub(crate) fn escape_js(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => r.push_str("\\\\"),
+            '"' | '\'' | '`' | '<' | '>' | '&' | '/' | '\u{2028}' | '\u{2029}' => {
+                r.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            c if c.is_control() => r.push_str(&format!("\\u{:04X}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r
+}
+
+pub(crate) fn escape_css(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() || !c.is_ascii() {
+            r.push(c);
+        } else {
+            r.push_str(&format!("\\{:X} ", c as u32));
+        }
+    }
+    r
 }
@@ -0,0 +1,37 @@
+#[cfg(test)]
+#[test]
+fn test_aggregate() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-aggregate/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        br#"<wd:session name="aggregate"><wd:update commit="true">
+    <collection name="sale"><field name="country">US</field><field name="price">100</field></collection>
+    <collection name="sale"><field name="country">UK</field><field name="price">30</field></collection>
+    <collection name="sale"><field name="country">US</field><field name="price">50</field></collection>
+    <collection name="sale"><field name="country">UK</field><field name="price"></field></collection>
+</wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="sale"><result var="p"
+><wd:aggregate var="a" result:var="p" collection="sale" field="price" group="field.country"
+><wd:json value:var="a" /></wd:aggregate></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"{"count":4,"sum":180,"avg":60,"min":30,"max":100,"groups":{"US":{"count":2,"sum":150,"avg":75,"min":50,"max":100},"UK":{"count":2,"sum":30,"avg":30,"min":30,"max":30}}}"#
+    );
+}
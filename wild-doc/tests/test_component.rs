@@ -0,0 +1,37 @@
+#[cfg(test)]
+#[test]
+fn test_component() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-component/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:define name="card" params="title,note"><div><h1><wd:print value:var="title" /></h1><wd:slot /><wd:slot name="footer">no footer</wd:slot></div></wd:define><wd:var title="outer"><wd:call name="card" title:var="input.title"><p><wd:print value:var="title" /></p></wd:call><wd:call name="card" title="second"><wd:slot name="footer">footer</wd:slot></wd:call></wd:var>"#,
+            br#"{"title":"first"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<div><h1>first</h1><p>outer</p>no footer</div><div><h1>second</h1>footer</div>"
+    );
+
+    let r = wd
+        .run(
+            br#"<wd:define name="panel"><section><wd:slot>empty</wd:slot></section></wd:define><wd:call name="panel">
+    <wd:slot name="footer">footer</wd:slot>
+</wd:call>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<section>empty</section>"
+    );
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+#[test]
+fn test_if() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-condition/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<wd:if value:var="input.a">a<wd:elseif value:var="input.b">b</wd:elseif><wd:else>else</wd:else></wd:if>"#;
+
+    let r = wd.run(xml, br#"{"a":true,"b":true}"#).unwrap();
+    assert_eq!(r.body(), b"a");
+
+    let r = wd.run(xml, br#"{"a":false,"b":true}"#).unwrap();
+    assert_eq!(r.body(), b"b");
+
+    let r = wd.run(xml, br#"{"a":false,"b":false}"#).unwrap();
+    assert_eq!(r.body(), b"else");
+
+    let xml =
+        br#"<wd:if value:var="input.a">a<wd:elseif value:var="input.b" />b<wd:else />else</wd:if>"#;
+
+    let r = wd.run(xml, br#"{"a":true,"b":true}"#).unwrap();
+    assert_eq!(r.body(), b"a");
+
+    let r = wd.run(xml, br#"{"a":false,"b":true}"#).unwrap();
+    assert_eq!(r.body(), b"b");
+
+    let r = wd.run(xml, br#"{"a":false,"b":false}"#).unwrap();
+    assert_eq!(r.body(), b"else");
+}
+
+#[cfg(test)]
+#[test]
+fn test_case() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-case/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<wd:case value:var="input.status"><wd:when in="200,204">ok</wd:when><wd:when min="300" max="399">redirect</wd:when><wd:when regex="^4" not="true">error</wd:when><wd:else>client error</wd:else></wd:case>"#;
+
+    let r = wd.run(xml, br#"{"status":204}"#).unwrap();
+    assert_eq!(r.body(), b"ok");
+
+    let r = wd.run(xml, br#"{"status":302}"#).unwrap();
+    assert_eq!(r.body(), b"redirect");
+
+    let r = wd.run(xml, br#"{"status":500}"#).unwrap();
+    assert_eq!(r.body(), b"error");
+
+    let r = wd.run(xml, br#"{"status":404}"#).unwrap();
+    assert_eq!(r.body(), b"client error");
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+#[test]
+fn test_error() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-error/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<div>
+    <wd:var hoge="1">
+        <wd:print value:expr="1 +" />
+    </wd:var>
+</div>"#;
+    match wd.run(xml, b"") {
+        Ok(_) => panic!("script error is not reported"),
+        Err(e) => {
+            assert!(matches!(e, WildDocError::Script { .. }));
+            assert!(e
+                .to_string()
+                .starts_with("3:9 <wd:print> expr script error: "));
+            assert_eq!(e.tag(), Some("wd:print"));
+            let location = e.location().unwrap();
+            assert_eq!(location.line, 3);
+            assert_eq!(location.column, 9);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_strict() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-strict/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<wd:search collection="person" create_collection_if_not_exists="true"><wresult var="p"></wresult></wd:search>"#;
+    assert!(wd.run(xml, b"").is_ok());
+
+    wd.set_strict(true);
+    match wd.run(xml, b"") {
+        Err(WildDocError::UnknownTag { location, tag }) => {
+            assert_eq!(tag, "wresult");
+            assert_eq!(location.column, 71);
+        }
+        _ => panic!("unknown tag is not reported"),
+    }
+
+    match wd.run(br#"<wd:for in:var="input"></wd:for>"#, b"") {
+        Err(WildDocError::MissingAttribute { tag, attribute, .. }) => {
+            assert_eq!(tag, "wd:for");
+            assert_eq!(attribute, "var");
+        }
+        _ => panic!("missing attribute is not reported"),
+    }
+
+    match wd.run(br#"<div><wd:else>x</wd:else></div>"#, b"") {
+        Err(e @ WildDocError::Tag { .. }) => {
+            assert_eq!(e.tag(), Some("wd:else"));
+            assert!(e
+                .to_string()
+                .ends_with("must be directly inside wd:if or wd:case"));
+        }
+        _ => panic!("misplaced wd:else is not reported"),
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(test)]
+#[test]
+fn test_escape() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-escape/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<p title:var="input.v"><wd:print value:var="input.v" /><wd:print value:var="input.v" raw="true" /></p><wd:print value:var="input.v" escape="url" /><script>var v="<wd:print value:var="input.v" />";</script><style>.<wd:print value:var="input.v" />{}</style>"#;
+    let input = br#"{"v":"<b a='1'>\"&"}"#;
+
+    let r = wd.run(xml, input).unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<p title="&lt;b a=&#039;1&#039;&gt;&quot;&amp;">&lt;b a=&#039;1&#039;&gt;&quot;&amp;<b a='1'>"&</p>%3Cb%20a%3D%271%27%3E%22%26<script>var v="\u003Cb a=\u00271\u0027\u003E\u0022\u0026";</script><style>.\3C b\20 a\3D \27 1\27 \3E \22 \26 {}</style>"#
+    );
+
+    wd.set_auto_escape(false);
+    let r = wd
+        .run(br#"<wd:print value:var="input.v" />"#, input)
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), r#"<b a='1'>"&"#);
+}
+
+#[cfg(test)]
+#[test]
+fn test_escape_attribute() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-escape-attribute/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<a href:var="input.url" onclick:var="input.v">x</a><img src:var="input.bad" />"#;
+    let r = wd
+        .run(
+            xml,
+            br#"{"url":"https://example.com/?a=1&b=2","v":"');alert(1)//","bad":" Java\tScript:alert(1)"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<a href="https://example.com/?a=1&amp;b=2" onclick="\u0027);alert(1)\u002F\u002F">x</a><img src="about:invalid" />"#
+    );
+
+    let r = wd
+        .run(
+            br#"<a href:var="input.url">x</a>"#,
+            br#"{"url":"/path/to:page?q=a:b#c"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<a href="/path/to:page?q=a:b#c">x</a>"#
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_escape_binary() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-escape-binary/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        br#"<wd:session name="escape"><wd:update commit="true">
+    <collection name="note"><field name="body" base64="true">PHNjcmlwdD54PC9zY3JpcHQ+/w</field></collection>
+</wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:record var="r" collection="note" row="1"><wd:print value:var="r.field.body" /></wd:record>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "&lt;script&gt;x&lt;/script&gt;\u{FFFD}"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_escape_custom_tag() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-escape-custom-tag/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let input = br#"{"tag":"a","v":"a\" onclick=\"alert(1)","url":"javascript:alert(1)","empty":"","name":"div onclick=alert(1)"}"#;
+    let r = wd
+        .run(
+            br#"<wd:tag wd-tag:name:var="input.tag" title:var="input.v" href:var="input.url" class="a &amp; b">x</wd:tag>"#,
+            input,
+        )
+        .unwrap();
+    let body = std::str::from_utf8(r.body()).unwrap();
+    assert!(body.starts_with("<a "));
+    assert!(body.contains(r#" title="a&quot; onclick=&quot;alert(1)""#));
+    assert!(body.contains(r#" href="about:invalid""#));
+    assert!(body.contains(r#" class="a &amp; b""#));
+    assert!(body.ends_with(">x</a>"));
+
+    for xml in [
+        br#"<wd:tag wd-tag:name:var="input.empty">x</wd:tag>"#.as_slice(),
+        br#"<wd:tag wd-tag:name:var="input.name">x</wd:tag>"#.as_slice(),
+    ] {
+        match wd.run(xml, input) {
+            Ok(r) => panic!("{}", std::str::from_utf8(r.body()).unwrap()),
+            Err(e) => assert!(e.to_string().contains("invalid tag name"), "{}", e),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+#[cfg(test)]
+#[test]
+fn test_export() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-export/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:json value:var="input" />
+<wd:csv value:var="input.list" fields="name,note" />"#,
+            br#"{"list":[{"name":"Noah","note":"a, b"},{"name":"Liam","note":"say \"hi\""}]}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "{\"list\":[{\"name\":\"Noah\",\"note\":\"a, b\"},{\"name\":\"Liam\",\"note\":\"say \\\"hi\\\"\"}]}\n\
+name,note\r\nNoah,\"a, b\"\r\nLiam,\"say \"\"hi\"\"\"\r\n"
+    );
+
+    wd.run(
+        br#"<wd:session name="export"><wd:update commit="true">
+    <collection name="person">
+        <field name="name">Noah</field>
+        <field name="country">US</field>
+    </collection>
+    <collection name="person">
+        <field name="name">Olivia</field>
+        <field name="country">UK</field>
+    </collection>
+</wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="person"><result var="p"><wd:csv value:var="p" collection="person" fields="name,country" header="false" /><wd:json value:var="p" collection="person" fields="name" /></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    let body = std::str::from_utf8(r.body()).unwrap();
+    assert!(body.starts_with("Noah,US\r\nOlivia,UK\r\n["));
+    assert!(body.contains(r#""field":{"name":"Noah"}"#));
+    assert!(body.contains(r#""field":{"name":"Olivia"}"#));
+
+    assert!(wd
+        .run(
+            br#"<wd:search collection="person"><result var="p"><wd:csv value:var="p" fields="name" /></result></wd:search>"#,
+            b"",
+        )
+        .is_err());
+
+    let r = wd
+        .run(
+            br#"<script>var data = <wd:json value:var="input" />;</script>"#,
+            r#"{"html":"</script><b>&amp;","separators":"\u2028\u2029"}"#.as_bytes(),
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<script>var data = {"html":"\u003C/script\u003E\u003Cb\u003E\u0026amp;","separators":"\u2028\u2029"};</script>"#
+    );
+}
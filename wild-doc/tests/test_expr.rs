@@ -0,0 +1,62 @@
+#[cfg(test)]
+#[test]
+fn test_expr() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-expr/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:print value:expr="input.price * 2 + 1" />
+<wd:print value:expr="input.price / 4" />
+<wd:print value:expr="'#' + input.items[input.i] + 1" />
+<wd:print value:expr="input.items[-1]" />
+<wd:if value:expr="input.price * 1.1 > 100 &amp;&amp; !input.admin">expensive</wd:if>
+<wd:if value:expr="(input.price &lt; 100 || input.admin) == false">not cheap</wd:if>"#,
+            br#"{"price":100,"admin":false,"i":1,"items":["a","b","c"]}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "201\n25\n#b1\nc\nexpensive\nnot cheap"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_expr_short_circuit() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-expr-short-circuit/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:if value:expr="input.x != null &amp;&amp; input.x > 1">x</wd:if>
+<wd:if value:expr="input.x == null || input.x > 1">null</wd:if>
+<wd:if value:expr="input.y != null &amp;&amp; input.y > 1">y</wd:if>
+<wd:print value:expr="input.x == null || input.x * 2" />"#,
+            br#"{"x":null,"y":2}"#,
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "\nnull\ny\ntrue");
+
+    let r = wd
+        .run(
+            br#"<wd:print value:expr="&quot;a&amp;&quot; + &#039;&lt;b&gt;&#039;" raw="true" />"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "a&<b>");
+}
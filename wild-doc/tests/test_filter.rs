@@ -0,0 +1,42 @@
+#[cfg(test)]
+#[test]
+fn test_filter() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-filter/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:print value:var="input.t" filter="date:%Y-%m-%d %H:%M" />
+<wd:print value:var="input.n" filter="number:2" />
+<wd:print value:var="input.n" filter="number" />
+<wd:print value:var="input.s" filter="truncate:5" />
+<wd:print value:var="input.s" filter="truncate:5,|lower" />
+<wd:print value:var="input.s" filter="upper" />
+<wd:print value:var="input.none" filter="default:none" />
+<wd:print value:var="input.o" filter="json" raw="true" />
+<wd:print value:var="input.s" filter="base64" />
+<wd:print value:var="input.s" filter="urlencode" />"#,
+            br#"{"t":1700000000,"n":-1234567.891,"s":"Hello World","none":null,"o":{"a":[1,2]}}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"2023-11-14 22:13
+-1,234,567.89
+-1,234,568
+Hello...
+hello
+HELLO WORLD
+none
+{"a":[1,2]}
+SGVsbG8gV29ybGQ=
+Hello%20World"#
+    );
+}
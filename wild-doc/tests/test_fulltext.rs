@@ -0,0 +1,104 @@
+#[cfg(test)]
+#[test]
+fn test_fulltext() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-fulltext/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        r#"<wd:session name="fulltext"><wd:update commit="true">
+    <collection name="article"><field name="title">東京都の天気</field></collection>
+    <collection name="article"><field name="title">Web server in Rust</field></collection>
+    <collection name="article"><field name="title">京都の観光</field></collection>
+</wd:update></wd:session>"#
+            .as_bytes(),
+        b"",
+    )
+    .unwrap();
+
+    let search = r#"<wd:search collection="article"><field name="title" method="fulltext" value:var="input.q" /><result var="p"
+><wd:for var="row" in:var="p"><wd:print value:var="row" />,</wd:for></result></wd:search>"#
+        .as_bytes();
+    let find = |wd: &mut WildDoc<IncludeLocal>, q: &str| {
+        let r = wd
+            .run(search, serde_json::json!({ "q": q }).to_string().as_bytes())
+            .unwrap();
+        std::str::from_utf8(r.body()).unwrap().to_owned()
+    };
+    assert_eq!(find(&mut wd, "京都"), "1,3,");
+    assert_eq!(find(&mut wd, "東京"), "1,");
+    assert_eq!(find(&mut wd, "RUST　web"), "2,");
+    assert_eq!(find(&mut wd, "ノ天気"), "1,");
+    assert_eq!(find(&mut wd, "rust tokyo"), "");
+    assert_eq!(find(&mut wd, ""), "");
+    assert_eq!(find(&mut wd, " 　"), "");
+
+    wd.run(
+        r#"<wd:session name="fulltext"><wd:update commit="true">
+    <collection name="article" row="2"><field name="title">東京のRust</field></collection>
+</wd:update></wd:session>"#
+            .as_bytes(),
+        b"",
+    )
+    .unwrap();
+    assert_eq!(find(&mut wd, "東京"), "1,2,");
+    assert_eq!(find(&mut wd, "web"), "");
+
+    wd.run(
+        r#"<wd:session name="fulltext" commit_on_close="true"><wd:update>
+    <collection name="article"><field name="title">大阪と京都</field></collection>
+</wd:update></wd:session>"#
+            .as_bytes(),
+        b"",
+    )
+    .unwrap();
+    assert_eq!(find(&mut wd, "京都"), "1,3,4,");
+    assert_eq!(find(&mut wd, "大阪"), "4,");
+
+    wd.run(
+        br#"<wd:update><collection name="article" row="1" delete="true" /></wd:update>"#,
+        b"",
+    )
+    .unwrap();
+    assert_eq!(find(&mut wd, "東京"), "2,");
+
+    wd.run(
+        br#"<wd:session name="fulltext"><wd:update commit="true"><collection name="article" row="3" delete="true" /></wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+    assert_eq!(find(&mut wd, "京都"), "4,");
+
+    let r = wd
+        .run(
+            r#"<wd:session name="fulltext" clear_on_close="true"><wd:update>
+    <collection name="article"><field name="title">東京駅</field></collection>
+    <collection name="article" row="4"><field name="title">神戸</field></collection>
+</wd:update><wd:search collection="article"><field name="title" method="fulltext" value="東京" /><result var="p"
+><wd:for var="row" in:var="p"><wd:print value:var="row" />,</wd:for></result></wd:search
+><wd:search collection="article"><field name="title" method="fulltext" value="大阪" /><result var="p"
+><wd:for var="row" in:var="p"><wd:print value:var="row" />,</wd:for></result></wd:search></wd:session>"#
+                .as_bytes(),
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "-1,2,");
+    assert_eq!(find(&mut wd, "東京"), "2,");
+    assert_eq!(find(&mut wd, "大阪"), "4,");
+
+    let r = wd
+        .run(
+            r#"<wd:search collection="article"><wide><field name="title" method="fulltext" value="大阪" /><row method="in" value="2" /></wide><result var="p"
+><wd:for var="row" in:var="p"><wd:print value:var="row" />,</wd:for></result></wd:search>"#
+                .as_bytes(),
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "2,4,");
+}
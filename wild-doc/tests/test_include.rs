@@ -0,0 +1,126 @@
+#[cfg(test)]
+#[test]
+fn test_include_recursion() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-include/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all("./wd-test-include/include/").unwrap();
+    std::fs::write(
+        "./wd-test-include/include/a.xml",
+        r#"a<wd:include src="b.xml" />"#,
+    )
+    .unwrap();
+    std::fs::write(
+        "./wd-test-include/include/b.xml",
+        r#"b<wd:include src="a.xml" />"#,
+    )
+    .unwrap();
+    std::fs::write(
+        "./wd-test-include/include/tree.xml",
+        r#"<wd:define name="tree" params="node"><ul><wd:for var="child" in:var="node.children"><li><wd:print value:var="child.name" /><wd:call name="tree" node:var="child" /></li></wd:for></ul></wd:define>"#,
+    )
+    .unwrap();
+    std::fs::write(
+        "./wd-test-include/include/deep.xml",
+        r#"<wd:include src:var="input.next" />"#,
+    )
+    .unwrap();
+
+    let mut wd = WildDoc::new(
+        dir,
+        IncludeLocal::new("./wd-test-include/include/"),
+        None,
+        1,
+    );
+
+    let r = wd
+        .run(
+            br#"<wd:include src="tree.xml" /><wd:call name="tree" node:var="input" />"#,
+            br#"{"children":[{"name":"a","children":[{"name":"b","children":[]}]}]}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<ul><li>a<ul><li>b<ul></ul></li></ul></li></ul>"
+    );
+
+    match wd.run(br#"<wd:include src="a.xml" />"#, b"") {
+        Err(WildDocError::IncludeCycle { chain, .. }) => {
+            assert_eq!(
+                chain.iter().map(|v| v.as_str()).collect::<Vec<_>>(),
+                vec!["a.xml", "b.xml", "a.xml"]
+            );
+        }
+        _ => panic!("expected IncludeCycle"),
+    }
+
+    match wd.run(
+        br#"<wd:define name="loop"><wd:call name="loop" /></wd:define><wd:call name="loop" />"#,
+        b"",
+    ) {
+        Err(WildDocError::IncludeDepth { max_depth, .. }) => {
+            assert_eq!(max_depth, 16);
+        }
+        _ => panic!("expected IncludeDepth"),
+    }
+
+    wd.set_max_include_depth(Some(0));
+    match wd.run(
+        br#"<wd:include src="deep.xml" />"#,
+        br#"{"next":"none.xml"}"#,
+    ) {
+        Err(WildDocError::IncludeDepth { max_depth, .. }) => {
+            assert_eq!(max_depth, 0);
+        }
+        _ => panic!("expected IncludeDepth"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_extends() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-extends/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all("./wd-test-extends/include/").unwrap();
+    std::fs::write(
+        "./wd-test-extends/include/layout.xml",
+        r#"<title><wd:block name="title">Default</wd:block></title><main><wd:block name="body" /></main>"#,
+    )
+    .unwrap();
+    std::fs::write(
+        "./wd-test-extends/include/mid.xml",
+        r#"<wd:extends src="layout.xml"><wd:block name="title">Mid/<wd:parent /></wd:block></wd:extends>"#,
+    )
+    .unwrap();
+
+    let mut wd = WildDoc::new(
+        dir,
+        IncludeLocal::new("./wd-test-extends/include/"),
+        None,
+        1,
+    );
+
+    let r = wd
+        .run(
+            br#"<wd:extends src="mid.xml"><wd:block name="title">Child/<wd:parent /></wd:block><wd:block name="body"><wd:print value:var="input.message" /></wd:block></wd:extends>"#,
+            br#"{"message":"Hello"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<title>Child/Mid/Default</title><main>Hello</main>"
+    );
+
+    let r = wd.run(br#"<wd:include src="layout.xml" />"#, b"").unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<title>Default</title><main></main>"
+    );
+}
@@ -0,0 +1,95 @@
+#[cfg(test)]
+#[test]
+fn test_loop() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-loop/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:for var="i" in:var="input.list" loop="l" offset="1" limit="3" step="2"><wd:print value:var="l.index1" />/<wd:print value:var="l.length" />:<wd:print value:var="i" /><wd:if value:var="l.last">.</wd:if> </wd:for>"#,
+            br#"{"list":[1,2,3,4,5,6,7,8,9]}"#,
+        )
+        .unwrap();
+    assert_eq!(r.body(), b"1/3:2 2/3:4 3/3:6. ");
+
+    let r = wd
+        .run(
+            br#"<wd:for var="i" in:var="input.list" loop="l"><wd:if value:var="l.odd">o</wd:if><wd:if value:var="l.even">e</wd:if></wd:for>"#,
+            br#"{"list":[1,2,3]}"#,
+        )
+        .unwrap();
+    assert_eq!(r.body(), b"oeo");
+
+    let r = wd
+        .run(
+            br#"<wd:for var="i" in:var="input.list"><wd:if value:var="input.skip"><wd:case value:var="i"><wd:when value="2"><wd:continue /></wd:when><wd:when value="4"><wd:break /></wd:when></wd:case></wd:if><wd:print value:var="i" /></wd:for>"#,
+            br#"{"list":[1,2,3,4,5],"skip":true}"#,
+        )
+        .unwrap();
+    assert_eq!(r.body(), b"13");
+
+    let r = wd
+        .run(
+            br#"<wd:for var="row" in:var="input.rows" loop="outer"><wd:for var="col" in:var="row" loop="inner"><wd:print value:var="inner.parent.index0" /><wd:print value:var="col" /></wd:for></wd:for>"#,
+            br#"{"rows":[["a","b"],["c"]]}"#,
+        )
+        .unwrap();
+    assert_eq!(r.body(), b"0a0b1c");
+
+    let xml = br#"<wd:for var="i" in:var="input.list" limit="x">i</wd:for>"#;
+    let input = br#"{"list":[1,2]}"#;
+    assert_eq!(wd.run(xml, input).unwrap().body(), b"ii");
+    assert!(wd
+        .run(
+            br#"<wd:for var="i" in:var="input.list" step="0">i</wd:for>"#,
+            input
+        )
+        .is_err());
+
+    wd.set_strict(true);
+    match wd.run(xml, input) {
+        Ok(_) => panic!("invalid limit is not reported"),
+        Err(e) => assert!(e
+            .to_string()
+            .ends_with("limit must be a non-negative integer: x")),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_limit() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-limit/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+    wd.set_max_iterations(Some(100));
+
+    match wd.run(br#"<wd:while continue="true">loop</wd:while>"#, b"") {
+        Err(WildDocError::LimitExceeded { tag, limit, .. }) => {
+            assert_eq!(tag, "wd:while");
+            assert!(matches!(limit, Limit::Iterations(100)));
+        }
+        _ => panic!("expected LimitExceeded"),
+    }
+
+    wd.set_max_iterations(None);
+    wd.set_time_limit(Some(std::time::Duration::from_millis(100)));
+    match wd.run(br#"<wd:while continue="true">loop</wd:while>"#, b"") {
+        Err(WildDocError::LimitExceeded { limit, .. }) => {
+            assert!(matches!(limit, Limit::Time(_)));
+        }
+        _ => panic!("expected LimitExceeded"),
+    }
+}
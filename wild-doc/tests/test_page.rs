@@ -0,0 +1,81 @@
+#[cfg(test)]
+#[test]
+fn test_page() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-page/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        br#"<wd:session name="page"><wd:update commit="true">
+    <collection name="person"><field name="name">Noah</field></collection>
+    <collection name="person"><field name="name">Liam</field></collection>
+    <collection name="person"><field name="name">Olivia</field></collection>
+    <collection name="person"><field name="name">Emma</field></collection>
+    <collection name="person"><field name="name">Ava</field></collection>
+</wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="person"><result var="p" order="field.name" page:var="input.page" per_page="2"
+><wd:print value:var="p.page" />/<wd:print value:var="p.pages" /> of <wd:print value:var="p.total" />:<wd:for var="row" in:var="p.rows"
+><wd:record var="r" collection="person" row:var="row"> <wd:print value:var="r.field.name" /></wd:record></wd:for
+></result></wd:search>"#,
+            br#"{"page":2}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "2/3 of 5: Liam Noah"
+    );
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="person"><result var="p" offset="3" limit="10"
+><wd:print value:var="p.rows.len" /> <wd:print value:var="p.limit" /></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "2 10");
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="person"><result var="p" order="field.name DESC" limit="2"
+><wd:for var="row" in:var="p.rows"><wd:record var="r" collection="person" row:var="row"><wd:print value:var="r.field.name" /> </wd:record></wd:for
+><wd:print value:var="p.total" /></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "Olivia Noah 5");
+
+    let r = wd
+        .run(
+            br#"<wd:session name="page_session" clear_on_close="true">
+    <wd:update commit="false">
+        <collection name="person"><field name="name">Zoe</field></collection>
+    </wd:update>
+    <wd:search collection="person"><result var="p" order="field.name DESC" limit="2"
+    ><wd:for var="row" in:var="p.rows"><wd:print value:var="row" />,</wd:for></result></wd:search>
+</wd:session>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap().trim(), "-1,3,");
+
+    wd.set_strict(true);
+    let r = wd
+        .run(
+            br#"<wd:search collection="person"><result var="p" limit="2"><wd:print value:var="p.len" /> <wd:print value:var="p.total" /></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "2 5");
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+#[test]
+fn test_sort() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-sort/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        br#"<wd:session name="sort"><wd:update commit="true">
+    <collection name="person"><field name="name">Noah</field></collection>
+    <collection name="person"><field name="name">Ava</field></collection>
+</wd:update></wd:session>"#,
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:session name="sort_session" clear_on_close="true">
+    <wd:update commit="false">
+        <collection name="person"><field name="name">Liam</field></collection>
+    </wd:update>
+    <wd:search collection="person"><result var="p"><wd:sort var="sorted" result:var="p" collection="person" order="field.name DESC"
+    ><wd:for var="row" in:var="sorted"><wd:print value:var="row" />,</wd:for></wd:sort></result></wd:search>
+</wd:session>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap().trim(), "1,-1,2,");
+
+    wd.set_strict(true);
+    let r = wd
+        .run(
+            br#"<wd:session name="sort_session" clear_on_close="true">
+    <wd:update commit="false">
+        <collection name="person"><field name="name">Liam</field></collection>
+    </wd:update>
+    <wd:search collection="person"><result var="p"><wd:sort var="sorted" result:var="p" order="field.name DESC"
+    ><wd:for var="row" in:var="sorted"><wd:print value:var="row" />,</wd:for></wd:sort></result></wd:search>
+</wd:session>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap().trim(), "1,-1,2,");
+}
+
+#[cfg(test)]
+#[test]
+fn test_sort_modifiers() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-sort-modifiers/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    wd.run(
+        r#"<wd:session name="sort"><wd:update commit="true">
+    <collection name="item"><field name="price">9</field><field name="name">カメラ</field></collection>
+    <collection name="item"><field name="price">10</field><field name="name">きく</field></collection>
+    <collection name="item"><field name="price"></field><field name="name">あめ</field></collection>
+</wd:update></wd:session>"#
+            .as_bytes(),
+        b"",
+    )
+    .unwrap();
+
+    let r = wd
+        .run(
+            br#"<wd:search collection="item"><result var="p"
+><wd:sort var="price" result:var="p" collection="item" order="field.price NUM DESC NULLS LAST"
+><wd:for var="row" in:var="price"><wd:print value:var="row" />,</wd:for></wd:sort>
+<wd:sort var="name" result:var="p" collection="item" order="field.name COLLATE ja"
+><wd:for var="row" in:var="name"><wd:print value:var="row" />,</wd:for></wd:sort
+></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "2,1,3,\n3,1,2,");
+
+    wd.set_strict(true);
+    let r = wd
+        .run(
+            br#"<wd:search collection="item"><result var="p"
+><wd:sort var="price" result:var="p" order="field.price NUM DESC NULLS LAST"
+><wd:for var="row" in:var="price"><wd:print value:var="row" />,</wd:for></wd:sort
+></result></wd:search>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "2,1,3,");
+}
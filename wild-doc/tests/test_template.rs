@@ -0,0 +1,199 @@
+#[cfg(test)]
+#[test]
+fn test_template() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-template/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<ul><wd:for var="i" in:var="input.list"><li><wd:print value:var="i" /></li><br></wd:for></ul><wd:comment><wd:print value="comment" /></wd:comment><wd:letitgo><wd:print value="letitgo" /></wd:letitgo>"#;
+
+    let r = wd.run(xml, br#"{"list":[1,2]}"#).unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<ul><li>1</li><br><li>2</li><br></ul><wd:print value="letitgo" />"#
+    );
+
+    let r = wd.run(xml, br#"{"list":[3]}"#).unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<ul><li>3</li><br></ul><wd:print value="letitgo" />"#
+    );
+
+    // The document compiled by the first run is reused.
+    let template = wd.compile(xml);
+    wd.run(xml, br#"{"list":[]}"#).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&template, &wd.compile(xml)));
+}
+
+#[cfg(test)]
+#[test]
+fn test_run_to_writer() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-writer/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let xml = br#"<wd:result_option var="title" value="list" /><wd:for var="i" in:var="input.list"><p><wd:print value:var="i" /></p></wd:for>"#;
+    let input = format!(
+        "{{\"list\":[{}]}}",
+        (0..2000)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+
+    let body = wd.run(xml, input.as_bytes()).unwrap().body().to_vec();
+
+    let mut written = Vec::new();
+    let options = wd
+        .run_to_writer(xml, input.as_bytes(), &mut written)
+        .unwrap();
+    assert_eq!(written, body);
+    assert_eq!(
+        options
+            .get(&"title".to_owned())
+            .unwrap()
+            .as_string()
+            .as_str(),
+        "list"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_capture() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-capture/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:capture var="pager"><nav><wd:print value:var="input.page" /></nav></wd:capture><wd:result_option var="pager" value:var="pager" /><wd:print value:var="pager" raw="true" /><main /><wd:print value:var="pager" raw="true" />"#,
+            br#"{"page":2}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<nav>2</nav><main /><nav>2</nav>"
+    );
+    assert_eq!(
+        r.options()
+            .get(&"pager".to_owned())
+            .unwrap()
+            .as_string()
+            .as_str(),
+        "<nav>2</nav>"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_whitespace() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-whitespace/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<p>
+    <wd:for var="i" in:var="input.list" wd:trim="all">
+        <wd:print value:var="i" />
+    </wd:for>
+</p>"#,
+            br#"{"list":[1,2]}"#,
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "<p>12</p>");
+
+    wd.set_minify(true);
+    let r = wd
+        .run(
+            br#"<div>
+    <p>a   b</p>
+    <pre>  x
+  y</pre>
+    <span>a</span>
+    <span>b</span>
+</div>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "<div><p>a b</p><pre>  x\n  y</pre> <span>a</span> <span>b</span> </div>"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_html5() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-html5/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+    wd.set_html5(true);
+
+    let r = wd
+        .run(
+            br#"<head><meta charset="utf-8"><link rel="stylesheet" href="a.css"></head><p><img src:var="input.src"></img>text</p><script>if (a < b && b > c) { x = "<wd:print value:var="input.v" />"; }</script><textarea><b></textarea>"#,
+            br#"{"src":"a.png","v":"<"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<head><meta charset="utf-8"><link rel="stylesheet" href="a.css"></head><p><img src="a.png">text</p><script>if (a < b && b > c) { x = "\u003C"; }</script><textarea><b></textarea>"#
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_xml() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-xml/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<rss><channel><link>http://x</link><item><link><wd:print value:var="input.url" /></link><source url="http://y">y</source><track>1</track></item></channel></rss>"#,
+            br#"{"url":"http://z"}"#,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<rss><channel><link>http://x</link><item><link>http://z</link><source url="http://y">y</source><track>1</track></item></channel></rss>"#
+    );
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+#[test]
+fn test_on() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-on/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:update><collection name="person"><field name="name">Noah</field></collection><wd:if value:var="input.notify"><wd:on var="u">row:<wd:print value:var="u.commit_rows.0.row" /></wd:on></wd:if></wd:update>"#,
+            br#"{"notify":true}"#,
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "row:1");
+
+    let r = wd
+        .run(
+            br#"<wd:update><collection name="person"><field name="name">Liam</field></collection><wd:if value:var="input.notify"><wd:on var="u">row:<wd:print value:var="u.commit_rows.0.row" /></wd:on></wd:if></wd:update>"#,
+            br#"{"notify":false}"#,
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "");
+
+    let r = wd
+        .run(br#"<p><wd:on var="u"><b>x</b></wd:on></p>"#, b"")
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<p><wd:on var="u"><b>x</b></wd:on></p>"#
+    );
+
+    let r = wd
+        .run(
+            br#"<wd:on var='u' title='say "hi" &amp; bye'>x</wd:on>"#,
+            b"",
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        r#"<wd:on var="u" title="say &quot;hi&quot; &amp; bye">x</wd:on>"#
+    );
+}
@@ -0,0 +1,56 @@
+#[cfg(test)]
+#[test]
+fn test_var() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-var/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let input = br#"{"map":{"a.b":"dot"},"rows":["x","y","z"],"i":1,"s":"abc"}"#;
+    let r = wd
+        .run(
+            br#"<wd:print value:var="input.map['a.b']" />,<wd:print value:var="input.rows[input.i]" />,<wd:print value:var="input.rows[-1]" />,<wd:print value:var="input.rows.len" />,<wd:print value:var="input.s.len" />,<wd:print value:var="input.user?.name" />,<wd:print value:var="input.none" />"#,
+            input,
+        )
+        .unwrap();
+    assert_eq!(
+        std::str::from_utf8(r.body()).unwrap(),
+        "dot,y,z,3,3,null,null"
+    );
+
+    wd.set_strict(true);
+    assert!(wd
+        .run(br#"<wd:print value:var="input.user?.name" />"#, input)
+        .is_ok());
+    match wd.run(br#"<wd:print value:var="input.none" />"#, input) {
+        Err(WildDocError::Script { script, .. }) => assert_eq!(script, "var"),
+        _ => panic!("missing path is not reported"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_set() {
+    use wild_doc::*;
+
+    let dir = "./wd-test-set/";
+    if std::path::Path::new(dir).exists() {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    std::fs::create_dir_all(dir).unwrap();
+
+    let mut wd = WildDoc::new(dir, IncludeLocal::new("./include/"), None, 1);
+
+    let r = wd
+        .run(
+            br#"<wd:set var="total" value="0" /><wd:set var="names" value="[]" /><wd:set var="user" value="{&quot;name&quot;:&quot;a&quot;}" /><wd:for var="row" in:var="input.rows"><wd:set var="total" increment:var="row.price" /><wd:set var="names" append:var="row.name" /></wd:for><wd:set var="user" merge:var="input.extra" /><wd:print value:var="total" />,<wd:print value:var="names.len" />,<wd:print value:var="names[-1]" />,<wd:print value:var="user.name" /><wd:print value:var="user.age" />"#,
+            br#"{"rows":[{"name":"x","price":10},{"name":"y","price":5}],"extra":{"age":20}}"#,
+        )
+        .unwrap();
+    assert_eq!(std::str::from_utf8(r.body()).unwrap(), "15,2,y,a20");
+}